@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+//! Compares the lookup tables and the control character of the algorithm with the implementations they replaced, run with `cargo bench`.
+//! The previous implementations are rebuilt here, so both the speed and the results can be compared.\
+//! Every benchmark is run [WARM_UP_RUNS] times without being timed, then timed [RUNS] times, reporting the fastest and the median run.
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use codicefiscale::algorithm::{control_character, even_value, homocodic_letter, odd_value};
+
+/// Number of calls in a timed run
+const ITERATIONS: u32 = 200_000;
+/// Number of runs discarded before timing, to fill the caches and let the processor reach its speed
+const WARM_UP_RUNS: usize = 3;
+/// Number of timed runs of every benchmark
+const RUNS: usize = 15;
+/// Number of random preliminary codes whose control character is compared
+const SAMPLES: usize = 1_000_000;
+const CHARACTERS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// The alphabet searched with `binary_search` for the values in the even positions
+fn alphabet() -> &'static Vec<char> {
+    static ALPHABET: OnceLock<Vec<char>> = OnceLock::new();
+    ALPHABET.get_or_init(|| ('A'..='Z').collect())
+}
+
+/// The map of the values in the odd positions
+fn odd_lookup_table() -> &'static HashMap<char, u32> {
+    static TABLE: OnceLock<HashMap<char, u32>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let values = [
+            1, 0, 5, 7, 9, 13, 15, 17, 19, 21, 2, 4, 18, 20, 11, 3, 6, 8, 12, 14, 16, 10, 22, 25,
+            24, 23,
+        ];
+        let digits = ('0'..='9').zip(values);
+        let letters = ('A'..='Z').zip(values);
+        digits.chain(letters).collect()
+    })
+}
+
+/// The map of the letters replacing the digits in a homocodic code
+fn homocodic_lookup_table() -> &'static HashMap<u32, char> {
+    static TABLE: OnceLock<HashMap<u32, char>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..10).zip("LMNPQRSTUV".chars()).collect())
+}
+
+fn map_odd_value(c: char) -> u32 {
+    *odd_lookup_table().get(&c.to_ascii_uppercase()).unwrap()
+}
+
+fn map_even_value(c: char) -> u32 {
+    let c = c.to_ascii_uppercase();
+    if c.is_ascii_digit() {
+        c.to_digit(10).unwrap()
+    } else {
+        alphabet().binary_search(&c).unwrap() as u32
+    }
+}
+
+fn map_homocodic_letter(digit: u32) -> char {
+    *homocodic_lookup_table().get(&digit).unwrap()
+}
+
+/// The control character computed with two passes over the code, one for the even positions and one for the odd ones
+fn two_pass_control_character(preliminary_code: &str) -> char {
+    let even_characters: Vec<char> = preliminary_code
+        .char_indices()
+        .filter(|c| c.0 % 2 == 1)
+        .map(|c| c.1)
+        .collect();
+    let even_sum: u32 = even_characters.into_iter().map(map_even_value).sum();
+    let odd_characters: Vec<char> = preliminary_code
+        .char_indices()
+        .filter(|c| c.0 % 2 == 0)
+        .map(|c| c.1)
+        .collect();
+    let odd_sum: u32 = odd_characters.into_iter().map(map_odd_value).sum();
+    alphabet()[((even_sum + odd_sum) % 26) as usize]
+}
+
+/// Generate `count` random preliminary codes of letters and digits with a xorshift generator, so every run uses the same ones
+fn random_preliminary_codes(count: usize) -> Vec<String> {
+    let characters = CHARACTERS.as_bytes();
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        characters[(state % characters.len() as u64) as usize] as char
+    };
+    (0..count)
+        .map(|_| (0..15).map(|_| next()).collect())
+        .collect()
+}
+
+/// The time of the fastest and of the median run of a benchmark
+struct Timing {
+    min: Duration,
+    median: Duration,
+}
+
+/// Call `f` [ITERATIONS] times in each of the [WARM_UP_RUNS] and [RUNS] runs, returning the time of the timed ones
+fn measure(mut f: impl FnMut() -> u32) -> Timing {
+    let mut run = || {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(f());
+        }
+        start.elapsed()
+    };
+    for _ in 0..WARM_UP_RUNS {
+        run();
+    }
+    let mut runs: Vec<Duration> = (0..RUNS).map(|_| run()).collect();
+    runs.sort();
+    Timing {
+        min: runs[0],
+        median: runs[RUNS / 2],
+    }
+}
+
+/// Print the time of an iteration with the previous and the current implementation, described by their labels, comparing their medians
+fn report(
+    name: &str,
+    (previous_label, previous): (&str, Timing),
+    (current_label, current): (&str, Timing),
+) {
+    let per_iteration = |d: Duration| d.as_nanos() as f64 / f64::from(ITERATIONS);
+    println!(
+        "{name}:\n  {previous_label}: min {:.1} ns, median {:.1} ns\n  {current_label}: min {:.1} ns, median {:.1} ns\n  {:.1}x faster",
+        per_iteration(previous.min),
+        per_iteration(previous.median),
+        per_iteration(current.min),
+        per_iteration(current.median),
+        previous.median.as_secs_f64() / current.median.as_secs_f64()
+    );
+}
+
+fn main() {
+    for c in CHARACTERS.chars().chain(CHARACTERS.to_lowercase().chars()) {
+        assert_eq!(odd_value(c), Some(map_odd_value(c)), "odd value of {c}");
+        assert_eq!(even_value(c), Some(map_even_value(c)), "even value of {c}");
+    }
+    for digit in 0..10 {
+        assert_eq!(homocodic_letter(digit), Some(map_homocodic_letter(digit)));
+    }
+
+    let characters: &[char] = &CHARACTERS.chars().collect::<Vec<char>>();
+    let sum_characters =
+        |value: fn(char) -> u32| move || characters.iter().map(|c| value(black_box(*c))).sum();
+    report(
+        "odd values of 36 characters",
+        ("with the maps", measure(sum_characters(map_odd_value))),
+        (
+            "with the arrays",
+            measure(sum_characters(|c| odd_value(c).unwrap())),
+        ),
+    );
+    report(
+        "even values of 36 characters",
+        ("with the maps", measure(sum_characters(map_even_value))),
+        (
+            "with the arrays",
+            measure(sum_characters(|c| even_value(c).unwrap())),
+        ),
+    );
+    let sum_letters = |letter: fn(u32) -> char| {
+        move || (0..10).map(|digit| letter(black_box(digit)) as u32).sum()
+    };
+    report(
+        "homocodic letters of 10 digits",
+        ("with the maps", measure(sum_letters(map_homocodic_letter))),
+        (
+            "with the arrays",
+            measure(sum_letters(|digit| homocodic_letter(digit).unwrap())),
+        ),
+    );
+
+    let codes = random_preliminary_codes(SAMPLES);
+    for code in &codes {
+        assert_eq!(
+            control_character(code),
+            Some(two_pass_control_character(code)),
+            "control character of {code}"
+        );
+    }
+    println!("control character: same result for {SAMPLES} random preliminary codes");
+    let mut codes_cycle = codes.iter().cycle();
+    let previous =
+        measure(|| two_pass_control_character(black_box(codes_cycle.next().unwrap())) as u32);
+    let mut codes_cycle = codes.iter().cycle();
+    let current =
+        measure(|| control_character(black_box(codes_cycle.next().unwrap())).unwrap() as u32);
+    report(
+        "control character of a preliminary code",
+        ("in two passes", previous),
+        ("in one pass", current),
+    );
+}
@@ -0,0 +1,79 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+// With the bundled-data feature, the Belfiore codes in gi_nazioni.json and gi_comuni.json are compiled into the library.
+// The files are searched in the package root, unless CODICEFISCALE_NATIONS_FILE and CODICEFISCALE_CITIES_FILE are set.
+fn main() {
+    if env::var_os("CARGO_FEATURE_BUNDLED_DATA").is_none() {
+        return;
+    }
+    let nations_path = data_file_path("CODICEFISCALE_NATIONS_FILE", "gi_nazioni.json");
+    let cities_path = data_file_path("CODICEFISCALE_CITIES_FILE", "gi_comuni.json");
+    let nations = load_codes(&nations_path, "denominazione_nazione", Some("0000"));
+    let cities = load_codes(&cities_path, "denominazione_ita", None);
+    let content = format!(
+        "static NATIONS: &[(&str, &str)] = &{nations:?};\nstatic CITIES: &[(&str, &str)] = &{cities:?};\n"
+    );
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("bundled_data.rs");
+    fs::write(out_path, content).expect("Can't write the bundled data!");
+}
+
+fn data_file_path(variable: &str, default: &str) -> PathBuf {
+    println!("cargo:rerun-if-env-changed={variable}");
+    let path = env::var_os(variable)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(default));
+    println!("cargo:rerun-if-changed={}", path.display());
+    path
+}
+
+/// Read the name and the Belfiore code of every entry, sorted by name in uppercase.\
+/// An empty code is replaced with `missing_code`, and the entries left without a valid code are skipped.
+fn load_codes(path: &Path, name_field: &str, missing_code: Option<&str>) -> Vec<(String, String)> {
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        panic!(
+            "The bundled-data feature needs {}, download it from https://www.gardainformatica.it/database-comuni-italiani",
+            path.display()
+        )
+    });
+    let entries: Vec<Value> = serde_json::from_str(&content)
+        .unwrap_or_else(|_| panic!("Invalid JSON in {}", path.display()));
+    let mut codes: Vec<(String, String)> = entries
+        .iter()
+        .filter_map(|e| {
+            let name = e[name_field]
+                .as_str()
+                .unwrap_or_default()
+                .trim()
+                .to_uppercase();
+            let code = e["codice_belfiore"].as_str().unwrap_or_default();
+            // Same fixes applied by populate_db, only the nations get the 0000 code
+            match (code, missing_code) {
+                ("", Some(missing_code)) => Some((name, missing_code.to_string())),
+                (code, _) if is_belfiore_code(code) => Some((name, code.to_string())),
+                _ => None,
+            }
+        })
+        .collect();
+    codes.sort();
+    codes
+}
+
+/// Same check of algorithm::is_belfiore_code, which the build script can't use
+fn is_belfiore_code(code: &str) -> bool {
+    let mut chars = code.chars();
+    chars.next().is_some_and(|c| c.is_ascii_uppercase())
+        && chars.clone().count() == 3
+        && chars.all(|c| c.is_ascii_digit())
+}
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE cities (
+  id SERIAL PRIMARY KEY,
+  city_name VARCHAR NOT NULL,
+  city_code VARCHAR NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE nations (
+  id SERIAL PRIMARY KEY,
+  nation_name VARCHAR NOT NULL,
+  nation_code VARCHAR NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE cities DROP COLUMN is_province;
+ALTER TABLE cities DROP COLUMN province
\ No newline at end of file
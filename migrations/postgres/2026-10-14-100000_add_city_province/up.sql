@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE cities ADD COLUMN province VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE cities ADD COLUMN is_province BOOLEAN NOT NULL DEFAULT FALSE
\ No newline at end of file
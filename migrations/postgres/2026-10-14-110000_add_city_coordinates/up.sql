@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE cities ADD COLUMN lat DOUBLE PRECISION;
+ALTER TABLE cities ADD COLUMN lon DOUBLE PRECISION
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE nations DROP COLUMN iso
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE cities ADD COLUMN alternative_name VARCHAR
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX cities_city_code;
+DROP INDEX nations_nation_code
\ No newline at end of file
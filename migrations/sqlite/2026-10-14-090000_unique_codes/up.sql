@@ -0,0 +1,5 @@
+-- Your SQL goes here
+DELETE FROM cities WHERE id NOT IN (SELECT MIN(id) FROM cities GROUP BY city_code);
+DELETE FROM nations WHERE id NOT IN (SELECT MIN(id) FROM nations GROUP BY nation_code);
+CREATE UNIQUE INDEX cities_city_code ON cities (city_code);
+CREATE UNIQUE INDEX nations_nation_code ON nations (nation_code)
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE cities DROP COLUMN lon;
+ALTER TABLE cities DROP COLUMN lat
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE cities ADD COLUMN lat REAL;
+ALTER TABLE cities ADD COLUMN lon REAL
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE nations ADD COLUMN iso VARCHAR NOT NULL DEFAULT ''
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE cities DROP COLUMN valid_until
\ No newline at end of file
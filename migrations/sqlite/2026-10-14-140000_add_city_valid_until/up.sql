@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE cities ADD COLUMN valid_until DATE
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE populate_stats (
+  id INTEGER PRIMARY KEY NOT NULL,
+  nations_without_code BIGINT NOT NULL,
+  skipped_nations BIGINT NOT NULL,
+  skipped_cities BIGINT NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,392 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use crate::utils::{is_consonant, is_vowel};
+
+const MONTH_LETTERS: [char; 12] = ['A', 'B', 'C', 'D', 'E', 'H', 'L', 'M', 'P', 'R', 'S', 'T'];
+
+/// Letters replacing the digits, from 0 to 9, in a homocodic code
+const HOMOCODIC_LETTERS: [char; 10] = ['L', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'U', 'V'];
+
+/// Values of the letters, from A to Z, in the odd positions of the code.\
+/// The digits from 0 to 9 have the same values of the letters from A to J.
+const ODD_VALUES: [u32; 26] = [
+    1, 0, 5, 7, 9, 13, 15, 17, 19, 21, 2, 4, 18, 20, 11, 3, 6, 8, 12, 14, 16, 10, 22, 25, 24, 23,
+];
+
+/// Get the three letters representing the given surname in the code: its consonants, then its vowels, padded with `X`.\
+/// `J`, `K`, `W`, `X` and `Y` are consonants, so `Y` is never taken as a vowel.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::surname_letters;
+///
+/// assert_eq!(surname_letters("Rossi"), ['R', 'S', 'S']);
+/// assert_eq!(surname_letters("Bove"), ['B', 'V', 'O']);
+/// assert_eq!(surname_letters("Re"), ['R', 'E', 'X']);
+/// assert_eq!(surname_letters("R"), ['R', 'X', 'X']);
+/// assert_eq!(surname_letters("D'Angelo"), ['D', 'N', 'G']);
+/// assert_eq!(surname_letters("De Luca"), ['D', 'L', 'C']);
+/// assert_eq!(surname_letters("Oe"), ['O', 'E', 'X']);
+/// ```
+///
+/// Foreign surnames follow the same rules:
+/// ```
+/// use codicefiscale::algorithm::surname_letters;
+///
+/// assert_eq!(surname_letters("Yamamoto"), ['Y', 'M', 'M']);
+/// assert_eq!(surname_letters("Wright"), ['W', 'R', 'G']);
+/// assert_eq!(surname_letters("Jax"), ['J', 'X', 'A']);
+/// assert_eq!(surname_letters("Kyu"), ['K', 'Y', 'U']);
+/// ```
+pub fn surname_letters(surname: &str) -> [char; 3] {
+    let mut letters = ['X'; 3];
+    let consonants = surname.chars().filter(is_consonant);
+    let vowels = surname.chars().filter(is_vowel);
+    for (letter, c) in letters.iter_mut().zip(consonants.chain(vowels)) {
+        *letter = c.to_ascii_uppercase();
+    }
+    letters
+}
+
+/// Get the three letters representing the given name in the code.\
+/// If the name has more than three consonants, the first, the third and the fourth are used.\
+/// With exactly three consonants all of them are used in order and the vowels are ignored, like for surnames.\
+/// Multiple names are treated as a single one, ignoring the spaces, apostrophes and hyphens between them.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::name_letters;
+///
+/// assert_eq!(name_letters("Giovanni"), ['G', 'N', 'N']);
+/// assert_eq!(name_letters("Mario"), ['M', 'R', 'A']);
+/// assert_eq!(name_letters("Al"), ['L', 'A', 'X']);
+/// assert_eq!(name_letters("Maria Grazia"), ['M', 'G', 'R']);
+/// assert_eq!(name_letters("Yamamoto"), ['Y', 'M', 'T']);
+/// assert_eq!(name_letters("Wright"), ['W', 'G', 'H']);
+/// assert_eq!(name_letters("Jax"), ['J', 'X', 'A']);
+/// assert_eq!(name_letters("Aia"), ['A', 'I', 'A']);
+/// assert_eq!(name_letters(""), ['X', 'X', 'X']);
+/// ```
+///
+/// The boundary between the two rules, where Gianfranco is the example of the official specification:
+/// ```
+/// use codicefiscale::algorithm::name_letters;
+///
+/// // Three consonants: all of them, even if the name has more vowels
+/// assert_eq!(name_letters("Marco"), ['M', 'R', 'C']);
+/// assert_eq!(name_letters("Silvia"), ['S', 'L', 'V']);
+/// // Four or more consonants: the first, the third and the fourth
+/// assert_eq!(name_letters("Patrizia"), ['P', 'R', 'Z']);
+/// assert_eq!(name_letters("Gianfranco"), ['G', 'F', 'R']);
+/// assert_eq!(name_letters("Francesco"), ['F', 'N', 'C']);
+/// assert_eq!(name_letters("Cristina"), ['C', 'S', 'T']);
+/// // Less than three consonants: the vowels follow them
+/// assert_eq!(name_letters("Luca"), ['L', 'C', 'U']);
+/// assert_eq!(name_letters("Ugo"), ['G', 'U', 'O']);
+/// ```
+pub fn name_letters(name: &str) -> [char; 3] {
+    let mut consonants = name.chars().filter(is_consonant);
+    if let (Some(first), Some(_), Some(third), Some(fourth)) = (
+        consonants.next(),
+        consonants.next(),
+        consonants.next(),
+        consonants.next(),
+    ) {
+        return [first, third, fourth].map(|c| c.to_ascii_uppercase());
+    }
+    surname_letters(name)
+}
+
+/// Checks if the given surname or name has less than three letters, so [surname_letters] and [name_letters] pad it with `X`
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::is_padded;
+///
+/// assert!(is_padded("Re"));
+/// assert!(is_padded("Al"));
+/// assert!(!is_padded("Rossi"));
+/// assert!(!is_padded("Ida"));
+/// ```
+pub fn is_padded(name: &str) -> bool {
+    name.chars()
+        .filter(|c| is_consonant(c) || is_vowel(c))
+        .count()
+        < 3
+}
+
+/// Checks if the given code has the format of a Belfiore code, an uppercase letter followed by three digits like `H501`
+pub fn is_belfiore_code(code: &str) -> bool {
+    let mut chars = code.chars();
+    chars.next().is_some_and(|c| c.is_ascii_uppercase())
+        && chars.clone().count() == 3
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+/// Get the last two digits of the given year, as written in the code
+pub fn year_digits(year: i32) -> [char; 2] {
+    let year = year.rem_euclid(100) as u8;
+    [(b'0' + year / 10) as char, (b'0' + year % 10) as char]
+}
+
+/// Get the letter representing the given month, from 1 to 12, in the code
+pub const fn month_letter(month: u32) -> Option<char> {
+    match month {
+        1..=12 => Some(MONTH_LETTERS[month as usize - 1]),
+        _ => None,
+    }
+}
+
+/// Get the month, from 1 to 12, represented by the given letter in the code, ignoring the case.\
+/// Returns `None` for the letters not used for the months, like `F`.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::month_from_letter;
+///
+/// assert_eq!(month_from_letter('A'), Some(1));
+/// assert_eq!(month_from_letter('t'), Some(12));
+/// assert_eq!(month_from_letter('F'), None);
+/// ```
+pub const fn month_from_letter(c: char) -> Option<u32> {
+    match c.to_ascii_uppercase() {
+        'A' => Some(1),
+        'B' => Some(2),
+        'C' => Some(3),
+        'D' => Some(4),
+        'E' => Some(5),
+        'H' => Some(6),
+        'L' => Some(7),
+        'M' => Some(8),
+        'P' => Some(9),
+        'R' => Some(10),
+        'S' => Some(11),
+        'T' => Some(12),
+        _ => None,
+    }
+}
+
+/// Get the number representing the birth day in the code, which is increased by 40 for women.\
+/// Returns `None` if the day is not between 1 and 31.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::day_number;
+///
+/// assert_eq!(day_number(1, false), Some(1));
+/// assert_eq!(day_number(31, true), Some(71));
+/// assert_eq!(day_number(0, false), None);
+/// assert_eq!(day_number(32, true), None);
+/// ```
+pub const fn day_number(day: u32, is_female: bool) -> Option<u32> {
+    match (day, is_female) {
+        (1..=31, true) => Some(day + 40),
+        (1..=31, false) => Some(day),
+        _ => None,
+    }
+}
+
+/// Get the letter replacing the given digit in a homocodic code
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::homocodic_letter;
+///
+/// let letters: String = (0..10).filter_map(homocodic_letter).collect();
+/// assert_eq!(letters, "LMNPQRSTUV");
+/// assert_eq!(homocodic_letter(10), None);
+/// ```
+pub const fn homocodic_letter(digit: u32) -> Option<char> {
+    match digit {
+        0..=9 => Some(HOMOCODIC_LETTERS[digit as usize]),
+        _ => None,
+    }
+}
+
+/// Get the digit replaced by the given letter in a homocodic code
+pub const fn homocodic_digit(c: char) -> Option<u32> {
+    match c {
+        'L' => Some(0),
+        'M' => Some(1),
+        'N' => Some(2),
+        'P' => Some(3),
+        'Q' => Some(4),
+        'R' => Some(5),
+        'S' => Some(6),
+        'T' => Some(7),
+        'U' => Some(8),
+        'V' => Some(9),
+        _ => None,
+    }
+}
+
+/// Replace the last `substitution_depth` digits of the preliminary code with their homocodic letters
+pub fn homocodic_preliminary_code(
+    preliminary_code: [char; 15],
+    substitution_depth: u32,
+) -> [char; 15] {
+    let mut preliminary_code = preliminary_code;
+    let digits = preliminary_code
+        .iter_mut()
+        .rev()
+        .filter(|c| c.is_ascii_digit())
+        .take(substitution_depth as usize);
+    for c in digits {
+        if let Some(letter) = c.to_digit(10).and_then(homocodic_letter) {
+            *c = letter;
+        }
+    }
+    preliminary_code
+}
+
+/// Get the value of a character in an odd position, counting from 1, for the control character
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::odd_value;
+///
+/// let values: Vec<u32> = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars().filter_map(odd_value).collect();
+/// assert_eq!(values, [1, 0, 5, 7, 9, 13, 15, 17, 19, 21, 2, 4, 18, 20, 11, 3, 6, 8, 12, 14, 16, 10, 22, 25, 24, 23]);
+/// let values: Vec<u32> = "0123456789".chars().filter_map(odd_value).collect();
+/// assert_eq!(values, [1, 0, 5, 7, 9, 13, 15, 17, 19, 21]);
+/// assert_eq!(odd_value('m'), Some(18));
+/// assert_eq!(odd_value('-'), None);
+/// ```
+pub const fn odd_value(c: char) -> Option<u32> {
+    match c.to_ascii_uppercase() {
+        c @ '0'..='9' => Some(ODD_VALUES[c as usize - '0' as usize]),
+        c @ 'A'..='Z' => Some(ODD_VALUES[c as usize - 'A' as usize]),
+        _ => None,
+    }
+}
+
+/// Get the value of a character in an even position, counting from 1, for the control character
+pub const fn even_value(c: char) -> Option<u32> {
+    match c.to_ascii_uppercase() {
+        c @ '0'..='9' => Some(c as u32 - '0' as u32),
+        c @ 'A'..='Z' => Some(c as u32 - 'A' as u32),
+        _ => None,
+    }
+}
+
+/// Compute the control character of the given preliminary code.\
+/// Returns `None` if the code contains a character that isn't an ASCII letter or digit.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::control_character;
+///
+/// assert_eq!(control_character("RSSMRA80A01H501"), Some('U'));
+/// assert_eq!(control_character("rssmra80a01h501"), Some('U'));
+/// assert_eq!(control_character("RSSMRA80A01H50M"), Some('M'));
+/// assert_eq!(control_character("BNCLGU99T71Z112"), Some('Q'));
+/// assert_eq!(control_character("DNGMGR68B69F205"), Some('W'));
+/// assert_eq!(control_character("XXXXXX00A01A000"), Some('S'));
+/// assert_eq!(control_character("RSSMRA80A01H50-"), None);
+/// ```
+///
+/// The single pass gives the same results of computing the sums of the even and the odd positions separately:
+/// ```
+/// use codicefiscale::algorithm::{control_character, even_value, odd_value};
+///
+/// let two_passes = |code: &str| {
+///     let even: u32 = code.chars().skip(1).step_by(2).map(|c| even_value(c).unwrap()).sum();
+///     let odd: u32 = code.chars().step_by(2).map(|c| odd_value(c).unwrap()).sum();
+///     (b'A' + ((even + odd) % 26) as u8) as char
+/// };
+/// let characters = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+/// for _ in 0..100_000 {
+///     let code: String = (0..15)
+///         .map(|_| {
+///             state ^= state << 13;
+///             state ^= state >> 7;
+///             state ^= state << 17;
+///             characters[(state % characters.len() as u64) as usize] as char
+///         })
+///         .collect();
+///     assert_eq!(control_character(&code), Some(two_passes(&code)), "{code}");
+/// }
+/// ```
+pub fn control_character(preliminary_code: &str) -> Option<char> {
+    control_character_of(preliminary_code.chars())
+}
+
+fn control_character_of(preliminary_code: impl IntoIterator<Item = char>) -> Option<char> {
+    let mut sum = 0;
+    for (i, c) in preliminary_code.into_iter().enumerate() {
+        sum += if i % 2 == 0 {
+            odd_value(c)?
+        } else {
+            even_value(c)?
+        };
+    }
+    Some((b'A' + (sum % 26) as u8) as char)
+}
+
+/// Compute the preliminary code, the first 15 characters of the code, from the given data.\
+/// The birth date is a `(year, month, day)` tuple and the location is the Belfiore code of the birth place.\
+/// Returns `None` if the month is not between 1 and 12, the day is not between 1 and 31 or the location code is not 4 characters long.
+pub fn preliminary_code(
+    surname: &str,
+    name: &str,
+    birth_date: (i32, u32, u32),
+    is_female: bool,
+    location_code: &str,
+) -> Option<[char; 15]> {
+    let (year, month, day) = birth_date;
+    let mut code = ['0'; 15];
+    code[0..3].copy_from_slice(&surname_letters(surname));
+    code[3..6].copy_from_slice(&name_letters(name));
+    code[6..8].copy_from_slice(&year_digits(year));
+    code[8] = month_letter(month)?;
+    let day = day_number(day, is_female)?;
+    code[9] = char::from_digit(day / 10 % 10, 10)?;
+    code[10] = char::from_digit(day % 10, 10)?;
+    let mut location = location_code.chars();
+    for c in code[11..15].iter_mut() {
+        *c = location.next()?;
+    }
+    if location.next().is_some() {
+        return None;
+    }
+    Some(code)
+}
+
+/// Compute the whole code from the given data, as in [preliminary_code]
+///
+/// # Examples
+/// ```
+/// use codicefiscale::algorithm::code;
+///
+/// let code_string = |surname, name, birth_date, is_female, location_code| {
+///     code(surname, name, birth_date, is_female, location_code).map(String::from_iter)
+/// };
+/// assert_eq!(code_string("Rossi", "Mario", (1980, 1, 1), false, "H501").unwrap(), "RSSMRA80A01H501U");
+/// assert_eq!(code_string("Bianchi", "Luigi", (1999, 12, 31), true, "Z112").unwrap(), "BNCLGU99T71Z112Q");
+/// assert_eq!(code_string("D'Angelo", "Maria Grazia", (1968, 2, 29), true, "F205").unwrap(), "DNGMGR68B69F205W");
+/// assert_eq!(code_string("Re", "Al", (2004, 12, 31), true, "Z112").unwrap(), "REXLAX04T71Z112Z");
+/// assert_eq!(code_string("Yamamoto", "Kyu", (1930, 1, 1), false, "Z219").unwrap(), "YMMKYU30A01Z219M");
+/// assert_eq!(code_string("Rossi", "Mario", (1980, 13, 1), false, "H501"), None);
+/// assert_eq!(code_string("Rossi", "Mario", (1980, 1, 32), false, "H501"), None);
+/// assert_eq!(code_string("Rossi", "Mario", (1980, 1, 1), false, "H50"), None);
+/// ```
+pub fn code(
+    surname: &str,
+    name: &str,
+    birth_date: (i32, u32, u32),
+    is_female: bool,
+    location_code: &str,
+) -> Option<[char; 16]> {
+    let preliminary_code = preliminary_code(surname, name, birth_date, is_female, location_code)?;
+    let control = control_character_of(preliminary_code)?;
+    let mut code = ['0'; 16];
+    code[..15].copy_from_slice(&preliminary_code);
+    code[15] = control;
+    Some(code)
+}
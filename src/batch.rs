@@ -0,0 +1,339 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::resolver::LocationResolver;
+use crate::{code_matches, generate_person_code, Language, PersonRecord, Sex};
+
+/// How [generate_csv_with] uses the rows of the input file
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// Generate the code of every row
+    #[default]
+    Generate,
+    /// Generate the code of every row and compare it with the one in the `code` column, accepting its homocodic versions
+    Check,
+}
+
+/// The options of [generate_csv_with]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct BatchOptions {
+    pub mode: BatchMode,
+    /// Collect the codes generated by more rows in [BatchSummary::collisions]
+    pub check_collisions: bool,
+}
+
+/// A code generated by more rows, whose people need a homocodic code to be told apart
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Collision {
+    pub code: String,
+    /// The lines of the input file with these people, the header being the first one
+    pub lines: Vec<u64>,
+}
+
+/// Number of rows processed by [generate_csv]
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct BatchSummary {
+    /// Rows whose code has been generated
+    pub generated: u64,
+    /// Rows reported with an error in the output
+    pub failed: u64,
+    /// Rows whose code matches the generated one, with [BatchMode::Check]
+    pub passed: u64,
+    /// Rows whose code doesn't match the generated one, with [BatchMode::Check]
+    pub mismatched: u64,
+    /// Codes generated by more rows, sorted by their first line, if [BatchOptions::check_collisions] is set
+    pub collisions: Vec<Collision>,
+}
+/// Represents an error reading or writing the CSV files, which stops [generate_csv].\
+/// The errors of the input, even the ones reading it, are [CsvError::Csv], while the ones of the output are [CsvError::Write].
+#[derive(Error, Debug)]
+pub enum CsvError {
+    #[error("Invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Can't write the output: {0}")]
+    Write(#[from] io::Error),
+    #[error("The input already has the {0} column, which is added to the output")]
+    ExistingColumn(String),
+}
+
+/// The columns added to the input rows in the output
+const OUTPUT_COLUMNS: [&str; 3] = ["generated_code", "status", "error"];
+
+impl CsvError {
+    /// Get the message of the error in the given language, the same of [Display](std::fmt::Display) for [Language::English].\
+    /// The messages coming from the CSV parser are left in English.
+    pub fn localized_message(&self, language: Language) -> String {
+        if language == Language::English {
+            return self.to_string();
+        }
+        match self {
+            CsvError::Csv(e) => format!("CSV non valido: {e}"),
+            CsvError::Write(e) => format!("Impossibile scrivere l'output: {e}"),
+            CsvError::ExistingColumn(column) => {
+                format!("L'input ha già la colonna {column}, che viene aggiunta all'output")
+            }
+        }
+    }
+}
+
+/// Represents a row of the input file of [generate_csv_with]
+#[derive(Deserialize)]
+struct InputRecord {
+    name: String,
+    surname: String,
+    sex: String,
+    nation: String,
+    /// The birth city, which can be left out for people born outside Italy
+    #[serde(default)]
+    city: String,
+    /// The initials of the province of the birth city, needed only to choose between the cities with the same name
+    #[serde(default)]
+    province: Option<String>,
+    birth_date: String,
+    /// The existing code, compared with the generated one with [BatchMode::Check]
+    #[serde(default)]
+    code: Option<String>,
+}
+
+/// Generate the codes of the people in the input CSV, with the columns `name`, `surname`, `sex`, `nation`, `city` and `birth_date`, finding the birth places with the given resolver as in [generate_iter](crate::generate_iter).\
+/// The optional `province` column has the initials of the province of the birth city, to choose between the cities with the same name.\
+/// Every row is written to the output as soon as it's processed, with three more columns: the `generated_code`, the `status`, `ok` or `error`, and the `error`.\
+/// A row that can't be processed is reported in its `error` column without stopping the generation, while the errors of the CSV files themselves stop it,
+/// as does an input already having one of the columns added, returning [CsvError::ExistingColumn].
+///
+/// # Examples
+/// ```
+/// use codicefiscale::batch::{generate_csv, CsvError};
+/// use codicefiscale::resolver::MapResolver;
+///
+/// let resolver = MapResolver::new()
+///     .with_nation("Italia", "0000")
+///     .with_nation("Germania", "Z112")
+///     .with_city("Roma", "H501");
+/// let input = "name,surname,sex,nation,city,birth_date
+/// Mario,Rossi,M,Italia,Roma,1980-01-01
+/// Mario,Rossi,M,Germania,,1980-01-01
+/// Mario,Rossi,X,Italia,Roma,1980-01-01
+/// Mario,Rossi,M,Italia,Atlantide,1980-01-01
+/// ";
+/// let mut output = Vec::new();
+/// let summary = generate_csv(&resolver, input.as_bytes(), &mut output).unwrap();
+/// assert_eq!((summary.generated, summary.failed), (2, 2));
+/// let output = String::from_utf8(output).unwrap();
+/// let mut lines = output.lines();
+/// assert_eq!(lines.next().unwrap(), "name,surname,sex,nation,city,birth_date,generated_code,status,error");
+/// assert_eq!(lines.next().unwrap(), "Mario,Rossi,M,Italia,Roma,1980-01-01,RSSMRA80A01H501U,ok,");
+/// assert_eq!(lines.next().unwrap(), "Mario,Rossi,M,Germania,,1980-01-01,RSSMRA80A01Z112F,ok,");
+/// assert_eq!(lines.next().unwrap(), "Mario,Rossi,X,Italia,Roma,1980-01-01,,error,Invalid sex X");
+/// assert_eq!(lines.next().unwrap(), "Mario,Rossi,M,Italia,Atlantide,1980-01-01,,error,City Atlantide not found");
+///
+/// let output_as_input = output.as_bytes();
+/// assert!(matches!(
+///     generate_csv(&resolver, output_as_input, Vec::new()),
+///     Err(CsvError::ExistingColumn(column)) if column == "generated_code"
+/// ));
+/// ```
+pub fn generate_csv<W: Write>(
+    resolver: &impl LocationResolver,
+    reader: impl Read,
+    writer: W,
+) -> Result<BatchSummary, CsvError> {
+    generate_csv_with(reader, writer, BatchOptions::default(), |person| {
+        generate_person_code(resolver, &person).map_err(|e| e.to_string())
+    })
+}
+
+/// Process the people in the input CSV as in [generate_csv], generating their codes with the given function, whose errors are written in the `error` column.\
+/// With [BatchMode::Check], the input also needs a `code` column: the `status` of the rows whose code is the generated one, even in a homocodic version, is `pass`, otherwise `fail`.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::batch::{generate_csv_with, BatchMode, BatchOptions, Collision};
+/// use codicefiscale::generate_code_from_codes;
+///
+/// let input = "name,surname,sex,nation,city,birth_date,code
+/// Mario,Rossi,M,Italia,Roma,1980-01-01,RSSMRA80A01H50MM
+/// Mario,Rossi,M,Italia,Roma,1980-01-01,RSSMRA80A01H501X
+/// Mario,Rossi,M,Italia,Roma,1980-01-01,
+/// ";
+/// let options = BatchOptions { mode: BatchMode::Check, check_collisions: true };
+/// let mut output = Vec::new();
+/// let summary = generate_csv_with(input.as_bytes(), &mut output, options, |person| {
+///     generate_code_from_codes(&person.name, &person.surname, person.sex, "H501", person.birth_date)
+///         .map_err(|e| e.to_string())
+/// })
+/// .unwrap();
+/// assert_eq!((summary.passed, summary.mismatched, summary.failed), (1, 1, 1));
+/// assert_eq!(summary.collisions, [Collision { code: "RSSMRA80A01H501U".to_string(), lines: vec![2, 3] }]);
+/// let output = String::from_utf8(output).unwrap();
+/// let mut lines = output.lines().skip(1);
+/// assert!(lines.next().unwrap().ends_with(",RSSMRA80A01H501U,pass,"));
+/// assert!(lines.next().unwrap().ends_with(",RSSMRA80A01H501U,fail,"));
+/// assert!(lines.next().unwrap().ends_with(",,error,Missing code to check"));
+/// ```
+///
+/// The lines of the rows are the ones of the input even after an unreadable row, while failing to write the output gives [CsvError::Write]:
+/// ```
+/// use codicefiscale::batch::{generate_csv_with, BatchOptions, Collision, CsvError};
+/// use std::io::{self, Write};
+///
+/// let row = "Mario,Rossi,M,Italia,Roma,1980-01-01\n";
+/// let header = b"name,surname,sex,nation,city,birth_date\n";
+/// let input = [&header[..], row.as_bytes(), b"M\xFFrio,Rossi\n", row.as_bytes()].concat();
+/// let options = BatchOptions { check_collisions: true, ..Default::default() };
+/// let mut output = Vec::new();
+/// let summary = generate_csv_with(&input[..], &mut output, options, |_| Ok("RSSMRA80A01H501U".to_string())).unwrap();
+/// assert_eq!((summary.generated, summary.failed), (2, 1));
+/// assert_eq!(summary.collisions, [Collision { code: "RSSMRA80A01H501U".to_string(), lines: vec![2, 4] }]);
+/// assert!(String::from_utf8(output).unwrap().contains(",,error,\"Unreadable row: "));
+///
+/// struct FullDisk;
+/// impl Write for FullDisk {
+///     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+///         Err(io::Error::other("no space left on device"))
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+/// // Enough rows to fill the buffer of the writer before the end
+/// let input = [row; 1000].concat();
+/// let input = format!("name,surname,sex,nation,city,birth_date\n{input}");
+/// let result = generate_csv_with(input.as_bytes(), FullDisk, BatchOptions::default(), |_| Ok("RSSMRA80A01H501U".to_string()));
+/// assert!(matches!(result, Err(CsvError::Write(_))));
+/// ```
+pub fn generate_csv_with<W: Write>(
+    reader: impl Read,
+    writer: W,
+    options: BatchOptions,
+    mut generate: impl FnMut(PersonRecord) -> Result<String, String>,
+) -> Result<BatchSummary, CsvError> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let mut writer = csv::Writer::from_writer(writer);
+    let headers = reader.headers()?.clone();
+    // A repeated column would make the output ambiguous for the programs reading the columns by name
+    if let Some(column) = OUTPUT_COLUMNS
+        .iter()
+        .find(|c| headers.iter().any(|h| h == **c))
+    {
+        return Err(CsvError::ExistingColumn(column.to_string()));
+    }
+    let mut output_headers = headers.clone();
+    output_headers.extend(OUTPUT_COLUMNS);
+    writer.write_record(&output_headers).map_err(output_error)?;
+    let mut summary = BatchSummary::default();
+    let mut lines_by_code: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut line = 1;
+    for record in reader.records() {
+        // An unreadable row is reported with empty fields, so the output keeps a row for every line of the input
+        let (mut record, result) = match record {
+            Ok(record) => {
+                line = record.position().map_or(line + 1, |p| p.line());
+                let result = generate_from_record(&record, &headers, options.mode, &mut generate);
+                (record, result)
+            }
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                line = e.position().map_or(line + 1, |p| p.line());
+                (
+                    csv::StringRecord::new(),
+                    Err(format!("Unreadable row: {e}")),
+                )
+            }
+        };
+        record.truncate(headers.len());
+        while record.len() < headers.len() {
+            record.push_field("");
+        }
+        match result {
+            Ok((code, check)) => {
+                summary.generated += 1;
+                let status = match check {
+                    None => "ok",
+                    Some(true) => {
+                        summary.passed += 1;
+                        "pass"
+                    }
+                    Some(false) => {
+                        summary.mismatched += 1;
+                        "fail"
+                    }
+                };
+                record.push_field(&code);
+                record.push_field(status);
+                record.push_field("");
+                if options.check_collisions {
+                    lines_by_code.entry(code).or_default().push(line);
+                }
+            }
+            Err(e) => {
+                summary.failed += 1;
+                record.push_field("");
+                record.push_field("error");
+                record.push_field(&e);
+            }
+        }
+        writer.write_record(&record).map_err(output_error)?;
+    }
+    writer.flush()?;
+    summary.collisions = lines_by_code
+        .into_iter()
+        .filter(|(_, lines)| lines.len() > 1)
+        .map(|(code, lines)| Collision { code, lines })
+        .collect();
+    summary.collisions.sort_by_key(|c| c.lines[0]);
+    Ok(summary)
+}
+
+/// Convert an error of the CSV writer, which can only come from the output, to [CsvError::Write]
+fn output_error(e: csv::Error) -> CsvError {
+    CsvError::Write(e.into())
+}
+
+/// Generate the code of the given row, along with whether it matches the existing one with [BatchMode::Check]
+fn generate_from_record(
+    record: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    mode: BatchMode,
+    generate: &mut impl FnMut(PersonRecord) -> Result<String, String>,
+) -> Result<(String, Option<bool>), String> {
+    let record: InputRecord = record
+        .deserialize(Some(headers))
+        .map_err(|e| e.to_string())?;
+    let existing_code = record.code.as_deref().map(str::trim).unwrap_or_default();
+    if mode == BatchMode::Check && existing_code.is_empty() {
+        return Err("Missing code to check".to_string());
+    }
+    let sex =
+        Sex::from_str(&record.sex, true).map_err(|_| format!("Invalid sex {}", record.sex))?;
+    let birth_date = NaiveDate::parse_from_str(&record.birth_date, "%F").map_err(|_| {
+        format!(
+            "Invalid birth date {}, it must be YYYY-MM-DD",
+            record.birth_date
+        )
+    })?;
+    let existing_code = existing_code.to_string();
+    let code = generate(PersonRecord {
+        name: record.name,
+        surname: record.surname,
+        sex,
+        birth_nation: record.nation,
+        birth_city: record.city,
+        birth_province: record.province,
+        birth_date,
+    })?;
+    let check = (mode == BatchMode::Check).then(|| code_matches(&code, &existing_code));
+    Ok((code, check))
+}
@@ -0,0 +1,58 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use crate::resolver::LocationResolver;
+
+include!(concat!(env!("OUT_DIR"), "/bundled_data.rs"));
+
+/// A [LocationResolver] using the bundled data
+pub struct BundledResolver;
+
+impl LocationResolver for BundledResolver {
+    fn resolve_city(&self, name: &str) -> Option<String> {
+        resolve_city(name)
+    }
+
+    fn resolve_nation(&self, name: &str) -> Option<String> {
+        resolve_nation(name)
+    }
+
+    /// The bundled data doesn't have the provinces, so all the cities with the given name are returned
+    fn resolve_cities(&self, name: &str, _province: Option<&str>) -> Vec<String> {
+        let name = name.trim().to_uppercase();
+        let index = CITIES.partition_point(|e| e.0 < name.as_str());
+        CITIES[index..]
+            .iter()
+            .take_while(|e| e.0 == name)
+            .map(|e| e.1.to_string())
+            .collect()
+    }
+
+    fn has_location_code(&self, code: &str) -> Option<bool> {
+        Some(CITIES.iter().chain(NATIONS).any(|e| e.1 == code))
+    }
+}
+
+/// Find the Belfiore code of the italian city with the given name, ignoring the case
+pub fn resolve_city(name: &str) -> Option<String> {
+    lookup(CITIES, name)
+}
+
+/// Find the Belfiore code of the nation with the given name, ignoring the case
+pub fn resolve_nation(name: &str) -> Option<String> {
+    lookup(NATIONS, name)
+}
+
+/// Search the name in a table sorted by name, returning the code of the first match
+fn lookup(table: &[(&str, &str)], name: &str) -> Option<String> {
+    let name = name.trim().to_uppercase();
+    let index = table.partition_point(|e| e.0 < name.as_str());
+    table
+        .get(index)
+        .filter(|e| e.0 == name)
+        .map(|e| e.1.to_string())
+}
@@ -5,9 +5,11 @@
  * Use of this source code is governed by BSD-3-Clause-Clear
  * license that can be found in the LICENSE file
  */
+use std::path::PathBuf;
+
 use chrono::NaiveDate;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use codicefiscale::Sex;
 
 use crate::parse_birth_date;
@@ -18,14 +20,37 @@ use crate::parse_birth_date;
 pub(crate) struct Cli {
     #[command(subcommand)]
     pub(crate) command: Commands,
+    ///Url of the database, the path of the file with SQLite [default: the DATABASE_URL environment variable]
+    #[arg(long, global = true)]
+    pub(crate) database_url: Option<String>,
+    ///Nations file loaded by the build-database command
+    #[arg(long, global = true, default_value = "gi_nazioni.json")]
+    pub(crate) nations_file: PathBuf,
+    ///Cities file loaded by the build-database command
+    #[arg(long, global = true, default_value = "gi_comuni.json")]
+    pub(crate) cities_file: PathBuf,
 }
 
 #[derive(Subcommand)]
 pub(crate) enum Commands {
     ///Generate the code
     Generate(GenerateArgs),
+    ///Check if a code is valid
+    Validate(ValidateArgs),
+    ///Print the data contained in a code
+    Parse(ParseArgs),
+    ///Print the homocodic versions of a code, replacing one more digit at a time from the right
+    Homocodic(HomocodicArgs),
+    ///Generate the codes of all the people in a CSV file
+    Batch(BatchArgs),
+    ///Print the name of the city or the nation with a Belfiore code
+    Lookup(LookupArgs),
+    ///Export the cities or the nations in the database to a CSV or JSON file
+    Export(ExportArgs),
     ///Build the nations and city database
-    BuildDatabase,
+    BuildDatabase(BuildDatabaseArgs),
+    ///Print the number of rows in the database, the rows collapsed or skipped when it was last populated, the codes used more than once and whether it is up to date
+    DbInfo,
     ///Build autocomplete scripts for all the shells supported and save them into the complete folder
     BuildComplete,
 }
@@ -39,13 +64,164 @@ pub(crate) struct GenerateArgs {
     ///Sex
     #[arg(value_enum)]
     pub(crate) sex: Sex,
-    ///Birth nation
-    pub(crate) nation: String,
-    ///Birth city (irrelevant if nation is different from italy, but still needed)
-    pub(crate) city: String,
     ///Birth date in format YYYY-MM-DD
     #[arg(value_parser = parse_birth_date)]
     pub(crate) birth_date: NaiveDate,
+    ///Birth nation
+    #[arg(
+        required_unless_present_any = ["nation_code", "city_code"],
+        conflicts_with = "nation_code"
+    )]
+    pub(crate) nation: Option<String>,
+    ///Birth city, needed only if the nation is Italy
+    #[arg(conflicts_with = "city_code")]
+    pub(crate) city: Option<String>,
+    ///Belfiore code of the birth nation, used instead of searching the nation
+    #[arg(long)]
+    pub(crate) nation_code: Option<String>,
+    ///Belfiore code of the birth city, used instead of searching the city
+    #[arg(long)]
+    pub(crate) city_code: Option<String>,
+    ///Initials of the province of the birth city, like CO for Como, to choose between cities with the same name
+    #[arg(long, conflicts_with = "city_code")]
+    pub(crate) province: Option<String>,
+    ///Ask which one to use when many cities or nations match the given name
+    #[arg(short, long)]
+    pub(crate) interactive: bool,
     ///Substitution depth for homocodic code
+    #[arg(short = 'd', long)]
     pub(crate) substitution_depth: Option<u32>,
+    ///Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub(crate) format: OutputFormat,
+    ///Print only the code, and the homocodic code on its own line if requested
+    #[arg(short, long)]
+    pub(crate) quiet: bool,
+    ///Letter case of the printed codes
+    #[arg(long, value_enum, default_value_t = LetterCase::Upper)]
+    pub(crate) case: LetterCase,
+    ///Separate the surname, name, birth date, birth place and control character of the printed codes with spaces
+    #[arg(long)]
+    pub(crate) group: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LetterCase {
+    ///Uppercase letters, the canonical form of the code
+    Upper,
+    ///Lowercase letters
+    Lower,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    ///Human readable text
+    Text,
+    ///JSON object with the code and the homocodic code
+    Json,
+}
+
+#[derive(Args)]
+pub(crate) struct ValidateArgs {
+    ///Code to validate
+    pub(crate) code: String,
+}
+
+#[derive(Args)]
+pub(crate) struct ParseArgs {
+    ///Code to parse
+    pub(crate) code: String,
+    ///Latest possible birth year, used to choose the century of the birth date [default: current year]
+    #[arg(long)]
+    pub(crate) pivot_year: Option<i32>,
+}
+
+#[derive(Args)]
+pub(crate) struct HomocodicArgs {
+    ///Code whose homocodic versions are printed
+    pub(crate) code: String,
+    ///Highest substitution depth, limited to the number of digits in the code
+    #[arg(short = 'd', long, default_value_t = 7)]
+    pub(crate) max_depth: u32,
+}
+
+#[derive(Args)]
+pub(crate) struct LookupArgs {
+    ///Belfiore code, like H501 for Roma
+    pub(crate) code: String,
+}
+
+#[derive(Args)]
+pub(crate) struct ExportArgs {
+    ///Table to export
+    #[arg(long, value_enum)]
+    pub(crate) table: Table,
+    ///Format of the output file
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub(crate) format: ExportFormat,
+    ///File where the table is written
+    #[arg(long)]
+    pub(crate) output: PathBuf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Table {
+    ///Italian cities
+    Cities,
+    ///Nations
+    Nations,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    ///CSV file with a header row
+    Csv,
+    ///JSON array of objects
+    Json,
+}
+
+#[derive(Args)]
+pub(crate) struct BatchArgs {
+    ///CSV file with the columns name, surname, sex, nation, city and birth_date, plus province to choose between the cities with the same name
+    #[arg(long)]
+    pub(crate) input: PathBuf,
+    ///CSV file where the input rows are written along with the generated code, the status and the error, if any
+    #[arg(long)]
+    pub(crate) output: PathBuf,
+    ///Report the rows producing the same code, which need a homocodic code to be told apart
+    #[arg(long)]
+    pub(crate) check_collisions: bool,
+    ///Check the existing codes in the code column instead of generating them, accepting their homocodic versions
+    #[arg(long)]
+    pub(crate) check: bool,
+}
+
+#[derive(Args)]
+pub(crate) struct BuildDatabaseArgs {
+    ///Delete all the nations and cities before loading them, instead of replacing the ones with the same code
+    #[arg(long)]
+    pub(crate) truncate: bool,
+    ///Load only the nations file, leaving the cities in the database untouched
+    #[arg(long, conflicts_with = "cities_only")]
+    pub(crate) nations_only: bool,
+    ///Load only the cities file, leaving the nations in the database untouched
+    #[arg(long)]
+    pub(crate) cities_only: bool,
+    ///Fail if more nations or more cities have the same code, instead of keeping only the first one
+    #[arg(long)]
+    pub(crate) fail_on_duplicates: bool,
+    ///Fail if a nation or a city has a code which isn't a letter followed by three digits, instead of skipping it
+    #[arg(long)]
+    pub(crate) strict: bool,
+    ///Schema of the nations and cities files
+    #[arg(long, value_enum, default_value_t = DataFormat::GardaInformatica)]
+    pub(crate) format: DataFormat,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum DataFormat {
+    ///The files by Garda Informatica
+    GardaInformatica,
+    ///JSON arrays of objects with the name and code fields
+    Minimal,
 }
@@ -5,113 +5,1340 @@
  * Use of this source code is governed by BSD-3-Clause-Clear
  * license that can be found in the LICENSE file
  */
-use crate::models::{City, Nation, NewCity, NewLoadedCity, NewLoadedNation, NewNation};
+use crate::algorithm::is_belfiore_code;
+use crate::models::{
+    City, MinimalLoadedCity, MinimalLoadedNation, Nation, NewCity, NewLoadedCity, NewLoadedNation,
+    NewNation,
+};
+use crate::resolver::LocationResolver;
 use crate::schema::cities::dsl::*;
 use crate::schema::nations::dsl::*;
+use crate::Language;
+use diesel::connection::DefaultLoadingMode;
+#[cfg(feature = "postgres")]
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
+#[cfg(feature = "pool")]
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+#[cfg(feature = "sqlite")]
 use diesel::sqlite::SqliteConnection;
+#[cfg(feature = "postgres")]
+use diesel::upsert::excluded;
 use diesel_migrations::MigrationHarness;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
-use std::{env, fs};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use std::{env, fs, io};
 use thiserror::Error;
 
 // Despite the errors found by rust-analyzer, the software compiles successfully
 
-const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("The sqlite and postgres features can't be enabled together, disable the default features to use postgres");
+
+#[cfg(feature = "sqlite")]
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+#[cfg(feature = "postgres")]
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+/// A connection to the database of the backend chosen with the `sqlite` or the `postgres` feature
+#[cfg(feature = "sqlite")]
+pub type DbConnection = SqliteConnection;
+/// A connection to the database of the backend chosen with the `sqlite` or the `postgres` feature
+#[cfg(feature = "postgres")]
+pub type DbConnection = PgConnection;
 
 #[derive(Error, Debug)]
 pub enum DbError {
     #[error("Database connection error")]
     NotExist,
+    #[error("A database with all nations and cities is needed.\nCreate one using build-database command and set the DATABASE_URL environment variable to the database url.")]
+    MissingUrl,
+    #[error("A database with all nations and cities is needed.\nCreate one using build-database command and set the DATABASE_URL environment variable to the database path.\nIf the database name is data.db and if is in the same path as this executable, the variable can be omitted.")]
+    NotCreated,
     #[error("Cities table empty!")]
     CitiesTableEmpty,
     #[error("Nations table empty!")]
     NationsTableEmpty,
+    #[error("Can't read {}: {source}", path.display())]
+    FileRead { path: PathBuf, source: io::Error },
+    #[error("Invalid JSON in {}: {source}", path.display())]
+    InvalidJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("More nations have the code {0}")]
+    DuplicateNationCode(String),
+    #[error("More cities have the code {0}")]
+    DuplicateCityCode(String),
+    #[error("{name} has the invalid code {code}")]
+    InvalidLocationCode { name: String, code: String },
+    #[error("Migration error: {0}")]
+    Migration(String),
+    #[error("The database is outdated, run the build-database command to update it")]
+    PendingMigrations,
+    #[error("Query error: {0}")]
+    Query(#[from] diesel::result::Error),
+    #[cfg(feature = "pool")]
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] PoolError),
 }
 
-/// Checks if the database is not empty
-pub fn check_db_not_empty() -> Result<(), DbError> {
-    let database_url = env::var("DATABASE_URL").unwrap_or("data.db".to_string());
-    if fs::read(&database_url).is_err() {
-        eprintln!(
-            "A database with all nations and cities is needed.\nCreate one using build-database command and set the DATABASE_URL environment variable to the database path.\nIf the database name is data.db and if is in the same path as this executable, the variable can be omitted."
-        );
+impl DbError {
+    /// Get the message of the error in the given language, the same of [Display](std::fmt::Display) for [Language::English].\
+    /// The messages coming from the JSON parser and the database are left in English.
+    pub fn localized_message(&self, language: Language) -> String {
+        if language == Language::English {
+            return self.to_string();
+        }
+        match self {
+            DbError::NotExist => "Errore di connessione al database".to_string(),
+            DbError::MissingUrl => "È necessario un database con tutte le nazioni e i comuni.\nCreane uno con il comando build-database e imposta la variabile d'ambiente DATABASE_URL all'url del database.".to_string(),
+            DbError::NotCreated => "È necessario un database con tutte le nazioni e i comuni.\nCreane uno con il comando build-database e imposta la variabile d'ambiente DATABASE_URL al percorso del database.\nSe il database si chiama data.db e si trova nella stessa cartella di questo eseguibile, la variabile può essere omessa.".to_string(),
+            DbError::CitiesTableEmpty => "La tabella dei comuni è vuota!".to_string(),
+            DbError::NationsTableEmpty => "La tabella delle nazioni è vuota!".to_string(),
+            DbError::FileRead { path, source } => {
+                format!("Impossibile leggere {}: {source}", path.display())
+            }
+            DbError::InvalidJson { path, source } => {
+                format!("JSON non valido in {}: {source}", path.display())
+            }
+            DbError::DuplicateNationCode(code) => format!("Più nazioni hanno il codice {code}"),
+            DbError::DuplicateCityCode(code) => format!("Più comuni hanno il codice {code}"),
+            DbError::InvalidLocationCode { name, code } => {
+                format!("{name} ha il codice non valido {code}")
+            }
+            DbError::Migration(e) => format!("Errore di migrazione: {e}"),
+            DbError::PendingMigrations => {
+                "Il database non è aggiornato, esegui il comando build-database per aggiornarlo"
+                    .to_string()
+            }
+            DbError::Query(e) => format!("Errore nella query: {e}"),
+            #[cfg(feature = "pool")]
+            DbError::Pool(e) => format!("Errore del pool di connessioni: {e}"),
+        }
+    }
+}
+
+/// A pool of connections to the database, shareable across threads
+#[cfg(feature = "pool")]
+pub type DbPool = Pool<ConnectionManager<DbConnection>>;
+
+/// How [populate_db] handles the rows already in the database
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum PopulateMode {
+    /// Replace the rows with the same code and keep the others
+    #[default]
+    Upsert,
+    /// Delete all the rows before inserting the new ones
+    Truncate,
+}
+
+/// How [populate_db] handles the nations sharing the same code in the nations file, and the cities sharing the same code in the cities file
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum DuplicateStrategy {
+    /// Keep the first nation or city with a code and skip the following ones, but keep ITALIA among the nations without a code
+    #[default]
+    Skip,
+    /// Stop with [DbError::DuplicateNationCode] or [DbError::DuplicateCityCode] without changing the database
+    Error,
+}
+
+/// How [populate_db] handles the nations and cities whose code isn't a Belfiore code, like `H501`, except for the `0000` code of the nations without one
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum InvalidCodeStrategy {
+    /// Skip them, listing them in [PopulateStats]
+    #[default]
+    Skip,
+    /// Stop with [DbError::InvalidLocationCode] without changing the database
+    Error,
+}
+
+/// The schema of the nations and cities files read by [populate_db_with_format]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum DataFormat {
+    /// The `gi_nazioni.json` and `gi_comuni.json` files by Garda Informatica
+    #[default]
+    GardaInformatica,
+    /// Arrays of objects with just the `name` and the `code` fields, plus the optional `iso` field for nations and `province` field for cities
+    Minimal,
+}
+
+/// Number of rows inserted by [populate_db]
+pub struct PopulateStats {
+    pub nations: usize,
+    pub cities: usize,
+    /// Nations without a code in the file, which all get the `0000` code so that only one of them is kept
+    pub nations_without_code: usize,
+    /// Nations skipped because their code was already used by a previous one
+    pub skipped_nations: usize,
+    /// Cities skipped because their code was already used by a previous one
+    pub skipped_cities: usize,
+    /// Nations skipped because their code isn't valid
+    pub rejected_nations: Vec<NewNation>,
+    /// Cities skipped because their code isn't valid
+    pub rejected_cities: Vec<NewCity>,
+}
+
+/// A [LocationResolver] using a database connection, which is locked for every search
+pub struct DbResolver {
+    conn: Mutex<DbConnection>,
+}
+
+impl DbResolver {
+    /// Create a resolver using the given connection
+    pub fn new(conn: DbConnection) -> Self {
+        DbResolver {
+            conn: Mutex::new(conn),
+        }
+    }
+}
+
+impl LocationResolver for DbResolver {
+    fn resolve_city(&self, name: &str) -> Option<String> {
+        let mut conn = self.conn.lock().ok()?;
+        let city = search_city(&mut conn, name, Some(1)).into_iter().next()?;
+        Some(city.city_code)
+    }
+
+    fn resolve_nation(&self, name: &str) -> Option<String> {
+        let mut conn = self.conn.lock().ok()?;
+        let nation = search_nation(&mut conn, name, Some(1)).into_iter().next()?;
+        Some(nation.nation_code)
+    }
+
+    fn resolve_cities(&self, name: &str, province_initials: Option<&str>) -> Vec<String> {
+        let Ok(mut conn) = self.conn.lock() else {
+            return Vec::new();
+        };
+        search_city_exact(&mut conn, name, province_initials)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.city_code)
+            .collect()
+    }
+
+    fn has_location_code(&self, code: &str) -> Option<bool> {
+        let mut conn = self.conn.lock().ok()?;
+        Some(
+            find_city_by_code(&mut conn, code).is_some()
+                || find_nation_by_code(&mut conn, code).is_some(),
+        )
+    }
+}
+
+#[cfg(feature = "pool")]
+impl LocationResolver for DbPool {
+    fn resolve_city(&self, name: &str) -> Option<String> {
+        let city = search_city_pooled(self, name, Some(1))
+            .ok()?
+            .into_iter()
+            .next()?;
+        Some(city.city_code)
+    }
+
+    fn resolve_nation(&self, name: &str) -> Option<String> {
+        let nation = search_nation_pooled(self, name, Some(1))
+            .ok()?
+            .into_iter()
+            .next()?;
+        Some(nation.nation_code)
+    }
+
+    fn resolve_cities(&self, name: &str, province_initials: Option<&str>) -> Vec<String> {
+        let Ok(mut conn) = self.get() else {
+            return Vec::new();
+        };
+        search_city_exact(&mut conn, name, province_initials)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.city_code)
+            .collect()
+    }
+
+    fn has_location_code(&self, code: &str) -> Option<bool> {
+        let mut conn = self.get().ok()?;
+        Some(
+            find_city_by_code(&mut conn, code).is_some()
+                || find_nation_by_code(&mut conn, code).is_some(),
+        )
+    }
+}
+
+/// Checks if the database at the given url exists and is not empty
+pub fn check_db_not_empty(database_url: &str) -> Result<(), DbError> {
+    #[cfg(feature = "sqlite")]
+    if fs::read(database_url).is_err() {
         return Err(DbError::NotExist);
     }
-    let mut conn = establish_connection();
-    let count: i64 = cities.count().get_result(&mut conn).unwrap();
+    let mut conn = DbConnection::establish(database_url).map_err(|_| DbError::NotExist)?;
+    check_migrations_applied(&mut conn)?;
+    let count: i64 = cities.count().get_result(&mut conn)?;
     if count <= 0 {
         return Err(DbError::CitiesTableEmpty);
     }
-    let count: i64 = nations.count().get_result(&mut conn).unwrap();
+    let count: i64 = nations.count().get_result(&mut conn)?;
     if count <= 0 {
         return Err(DbError::NationsTableEmpty);
     }
     Ok(())
 }
 
-/// Try to enstablish a connection with the database
-pub fn establish_connection() -> SqliteConnection {
-    let database_url = env::var("DATABASE_URL").unwrap_or("data.db".to_string());
-    SqliteConnection::establish(&database_url)
+/// Checks if the database at the url taken from the environment, as in [establish_connection_from_env], is not empty, as in [check_db_not_empty_or_explain]
+pub fn check_db_not_empty_from_env() -> Result<(), DbError> {
+    check_db_not_empty_or_explain(database_url_from_env().as_deref())
+}
+
+/// Checks if the database at the given url is not empty, as in [check_db_not_empty].\
+/// If there is no url, it returns [DbError::MissingUrl], and with SQLite, if the database doesn't exist, [DbError::NotCreated], whose messages explain how to create it.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::db_utils::*;
+///
+/// assert!(matches!(check_db_not_empty_or_explain(None), Err(DbError::MissingUrl)));
+/// let error = check_db_not_empty_or_explain(Some("/nonexistent/data.db")).unwrap_err();
+/// assert!(matches!(error, DbError::NotCreated));
+/// assert!(error.to_string().contains("build-database"));
+/// ```
+pub fn check_db_not_empty_or_explain(database_url: Option<&str>) -> Result<(), DbError> {
+    let Some(database_url) = database_url else {
+        return Err(DbError::MissingUrl);
+    };
+    let result = check_db_not_empty(database_url);
+    #[cfg(feature = "sqlite")]
+    if let Err(DbError::NotExist) = result {
+        return Err(DbError::NotCreated);
+    }
+    result
+}
+
+/// Get the url of the database from the `DATABASE_URL` environment variable.\
+/// With SQLite, it defaults to `data.db` in the current directory.
+pub fn database_url_from_env() -> Option<String> {
+    let database_url = env::var("DATABASE_URL").ok();
+    #[cfg(feature = "sqlite")]
+    let database_url = database_url.or(Some("data.db".to_string()));
+    database_url
+}
+
+/// Try to enstablish a connection with the database at the given url, the path of the file with SQLite
+pub fn establish_connection(database_url: &str) -> DbConnection {
+    DbConnection::establish(database_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
 
-/// Search the nation with the given name in the database
-pub fn search_nation(conn: &mut SqliteConnection, name: &str) -> Vec<Nation> {
-    nations
-        .filter(nation_name.like(name))
-        .limit(5)
+/// Try to enstablish a connection with the database at the url in the `DATABASE_URL` environment variable.\
+/// With SQLite, it defaults to `data.db` in the current directory.
+pub fn establish_connection_from_env() -> DbConnection {
+    let database_url =
+        database_url_from_env().expect("The DATABASE_URL environment variable must be set");
+    establish_connection(&database_url)
+}
+
+/// Open a new database in memory with all the migrations applied, useful for tests and ephemeral use
+///
+/// # Examples
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let nations_file = dir.join("nations.json");
+/// let cities_file = dir.join("cities.json");
+/// fs::write(&nations_file, r#"[{"name": "ITALIA", "code": ""}, {"name": "GERMANIA", "code": "Z112", "iso": "DE"}]"#).unwrap();
+/// let cities_json: Vec<String> = ["Marco", "Pietro", "Paolo", "Luca", "Vito", "Remo"]
+///     .iter()
+///     .enumerate()
+///     .map(|(i, saint)| format!(r#"{{"name": "San {saint}", "code": "A00{i}", "province": "RM"}}"#))
+///     .collect();
+/// fs::write(&cities_file, format!("[{}]", cities_json.join(","))).unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// let stats = populate_db_with_connection(
+///     &mut conn,
+///     Some(&nations_file),
+///     Some(&cities_file),
+///     DataFormat::Minimal,
+///     PopulateMode::default(),
+///     DuplicateStrategy::default(),
+///     InvalidCodeStrategy::default(),
+/// )
+/// .unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+/// assert_eq!((stats.nations, stats.cities), (2, 6));
+///
+/// // The names are matched with LIKE, ignoring the ASCII case and without adding wildcards
+/// assert_eq!(search_city(&mut conn, "san marco", None)[0].city_code, "A000");
+/// assert!(search_city(&mut conn, "San", None).is_empty());
+/// assert_eq!(search_city(&mut conn, "san %", None).len(), 6);
+/// assert_eq!(search_city(&mut conn, "san %", Some(DEFAULT_SEARCH_LIMIT)).len(), 5);
+/// assert_eq!(search_nation(&mut conn, "germania", None)[0].nation_code, "Z112");
+/// assert_eq!(search_nation(&mut conn, "italia", None)[0].nation_code, "0000");
+///
+/// assert_eq!(find_city_by_code(&mut conn, "A005").unwrap().city_name, "San Remo");
+/// assert_eq!(find_nation_by_code(&mut conn, "Z112").unwrap().iso, "DE");
+/// assert!(find_city_by_code(&mut conn, "Z112").is_none());
+/// ```
+#[cfg(feature = "sqlite")]
+pub fn establish_connection_in_memory() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:")
+        .unwrap_or_else(|_| panic!("Error connecting to the database in memory"));
+    run_migrations(&mut conn).expect("Error running the migrations");
+    conn
+}
+
+/// Apply the embedded migrations not yet applied to the database
+pub fn run_migrations(conn: &mut DbConnection) -> Result<(), DbError> {
+    conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| DbError::Migration(e.to_string()))?;
+    Ok(())
+}
+
+/// Checks that all the embedded migrations have been applied to the database
+pub fn check_migrations_applied(conn: &mut DbConnection) -> Result<(), DbError> {
+    let has_pending_migration = conn
+        .has_pending_migration(MIGRATIONS)
+        .map_err(|e| DbError::Migration(e.to_string()))?;
+    if has_pending_migration {
+        return Err(DbError::PendingMigrations);
+    }
+    Ok(())
+}
+
+/// Create a pool of connections to the database at the given url
+#[cfg(feature = "pool")]
+pub fn establish_pool(url: &str) -> Result<DbPool, DbError> {
+    let manager = ConnectionManager::<DbConnection>::new(url);
+    Ok(Pool::builder().build(manager)?)
+}
+
+/// Match a name column with a `LIKE` pattern ignoring the case, which needs `ILIKE` on PostgreSQL
+macro_rules! name_matches {
+    ($column:expr, $pattern:expr) => {{
+        #[cfg(feature = "sqlite")]
+        let matches = $column.like($pattern);
+        #[cfg(feature = "postgres")]
+        let matches = $column.ilike($pattern);
+        matches
+    }};
+}
+
+/// Escape the `%` and `_` wildcards of a `LIKE` pattern, and the `\` escaping them, so that the pattern only matches the given text
+fn escape_like_pattern(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Remove the spaces around the name and replace the ones between its words with a single space.\
+/// The searches ignore the case of the ASCII letters, so `  san   DONATO ` finds `San Donato`.\
+/// The accented letters are lowercased, as they are in the names of the cities, so `FORLÌ` finds `Forlì`.
+pub fn normalize_search_term(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() {
+                c
+            } else {
+                c.to_lowercase().next().unwrap_or(c)
+            }
+        })
+        .collect();
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Number of results returned by the searches of the command line interface
+pub const DEFAULT_SEARCH_LIMIT: i64 = 5;
+
+/// Search the nations with the given name in the database, returning at most `limit` results if given.\
+/// The name is normalized as in [normalize_search_term] and uppercased, like the names of the nations in the database.
+pub fn search_nation(conn: &mut DbConnection, name: &str, limit: Option<i64>) -> Vec<Nation> {
+    let start = Instant::now();
+    let name = normalize_search_term(name).to_uppercase();
+    let mut query = nations
+        .filter(name_matches!(nation_name, &name))
         .select(Nation::as_select())
-        .load(conn)
-        .expect("Error loading nation")
+        .into_boxed();
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    let found: Vec<Nation> = query.load(conn).expect("Error loading nation");
+    log!(
+        debug,
+        "Found {} nations named {name:?} in {:?}",
+        found.len(),
+        start.elapsed()
+    );
+    found
+}
+
+/// Search the nations with the given initials in the database, like `DE` for Germania, ignoring the case
+pub fn search_nation_by_iso(
+    conn: &mut DbConnection,
+    iso_code: &str,
+) -> Result<Vec<Nation>, DbError> {
+    Ok(nations
+        .filter(iso.eq(iso_code.trim().to_ascii_uppercase()))
+        .select(Nation::as_select())
+        .load(conn)?)
 }
 
-/// Search the italian city with the given name in the database
-pub fn search_city(conn: &mut SqliteConnection, name: &str) -> Vec<City> {
+/// Search the italian cities with the given name in the database, returning at most `limit` results if given.\
+/// The name is normalized as in [normalize_search_term] and matched against both the italian and the alternative name.
+pub fn search_city(conn: &mut DbConnection, name: &str, limit: Option<i64>) -> Vec<City> {
+    let start = Instant::now();
+    let name = normalize_search_term(name);
+    let mut query = cities
+        .filter(name_matches!(city_name, &name).or(name_matches!(alternative_name, &name)))
+        .select(City::as_select())
+        .into_boxed();
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    let found: Vec<City> = query.load(conn).expect("Error loading city");
+    log!(
+        debug,
+        "Found {} cities named {name:?} in {:?}",
+        found.len(),
+        start.elapsed()
+    );
+    found
+}
+
+/// Search the italian cities whose whole italian or alternative name is the given one, ignoring the case.\
+/// The name is normalized as in [normalize_search_term] and, if `province_initials` is given, only the cities in that province are returned.\
+/// Unlike [search_city], the `%` and `_` wildcards of `LIKE` are matched literally.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-exact-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let cities_file = dir.join("cities.json");
+/// fs::write(
+///     &cities_file,
+///     r#"[{"name": "Roma", "code": "H501", "province": "RM"}, {"name": "Peglio", "code": "G415", "province": "CO"}, {"name": "Peglio", "code": "G416", "province": "PU"}]"#,
+/// )
+/// .unwrap();
+/// let mut conn = establish_connection_in_memory();
+/// populate_db_with_connection(
+///     &mut conn,
+///     None,
+///     Some(&cities_file),
+///     DataFormat::Minimal,
+///     PopulateMode::default(),
+///     DuplicateStrategy::default(),
+///     InvalidCodeStrategy::default(),
+/// )
+/// .unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+///
+/// assert_eq!(search_city_exact(&mut conn, " ROMA ", None).unwrap()[0].city_code, "H501");
+/// assert!(search_city_exact(&mut conn, "Rom_", None).unwrap().is_empty());
+/// assert!(search_city_exact(&mut conn, "P%", None).unwrap().is_empty());
+/// assert_eq!(search_city_exact(&mut conn, "Peglio", None).unwrap().len(), 2);
+/// assert_eq!(search_city_exact(&mut conn, "Peglio", Some("pu")).unwrap()[0].city_code, "G416");
+/// ```
+pub fn search_city_exact(
+    conn: &mut DbConnection,
+    name: &str,
+    province_initials: Option<&str>,
+) -> Result<Vec<City>, DbError> {
+    let name = escape_like_pattern(&normalize_search_term(name));
+    let mut query = cities
+        .filter(
+            name_matches!(city_name, &name)
+                .escape('\\')
+                .or(name_matches!(alternative_name, &name).escape('\\')),
+        )
+        .select(City::as_select())
+        .into_boxed();
+    if let Some(initials) = province_initials {
+        query = query.filter(province.eq(initials.trim().to_ascii_uppercase()));
+    }
+    Ok(query.load(conn)?)
+}
+
+/// Search the italian cities with a name similar to the given one, ranked by their [Jaro-Winkler similarity](https://en.wikipedia.org/wiki/Jaro%E2%80%93Winkler_distance).\
+/// Every city is returned along with its score, from 0 to 1, the closest match first.\
+/// The score of a bilingual city is the highest between its two names.
+#[cfg(feature = "fuzzy")]
+pub fn search_city_fuzzy(
+    conn: &mut DbConnection,
+    name: &str,
+    limit: usize,
+) -> Result<Vec<(City, f64)>, DbError> {
+    let name = normalize_search_term(name).to_lowercase();
+    let mut candidates: Vec<(City, f64)> = cities
+        .select(City::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|c| {
+            let score = |candidate: &str| strsim::jaro_winkler(&name, &candidate.to_lowercase());
+            let alternative_score = c.alternative_name.as_deref().map_or(0.0, score);
+            let score = score(&c.city_name).max(alternative_score);
+            (c, score)
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+    candidates.truncate(limit);
+    Ok(candidates)
+}
+
+/// Search the italian cities in the province with the given initials, like `MI` for Milano
+pub fn search_city_by_province(
+    conn: &mut DbConnection,
+    initials: &str,
+) -> Result<Vec<City>, DbError> {
+    Ok(cities
+        .filter(province.eq(initials.trim().to_ascii_uppercase()))
+        .select(City::as_select())
+        .load(conn)?)
+}
+
+/// Search the nations with the given name using a connection from the pool
+#[cfg(feature = "pool")]
+pub fn search_nation_pooled(
+    pool: &DbPool,
+    name: &str,
+    limit: Option<i64>,
+) -> Result<Vec<Nation>, DbError> {
+    let mut conn = pool.get()?;
+    Ok(search_nation(&mut conn, name, limit))
+}
+
+/// Search the italian cities with the given name using a connection from the pool
+#[cfg(feature = "pool")]
+pub fn search_city_pooled(
+    pool: &DbPool,
+    name: &str,
+    limit: Option<i64>,
+) -> Result<Vec<City>, DbError> {
+    let mut conn = pool.get()?;
+    Ok(search_city(&mut conn, name, limit))
+}
+
+/// Find the italian city with the given Belfiore code in the database
+pub fn find_city_by_code(conn: &mut DbConnection, code: &str) -> Option<City> {
     cities
-        .filter(city_name.like(name))
-        .limit(5)
+        .filter(city_code.eq(code))
         .select(City::as_select())
-        .load(conn)
+        .first(conn)
+        .optional()
         .expect("Error loading city")
 }
 
-/// Populate the database using the data in the `gi_nazioni.json` and `gi_comuni.json`.\
-/// It also fixes some nation codes incompatibility.\
-/// These files can be obtained [here](https://www.gardainformatica.it/database-comuni-italiani).
-pub fn populate_db() {
-    let mut conn = establish_connection();
-    conn.run_pending_migrations(MIGRATIONS).unwrap();
-    let nations_file_content =
-        fs::read_to_string("gi_nazioni.json").expect("Error in nations json file opening");
-    let loaded_nations: Vec<NewLoadedNation> = serde_json::from_str(&nations_file_content).unwrap();
-    let loaded_nations: Vec<NewNation> = loaded_nations.into_iter().map(NewNation::from).collect();
-    let loaded_nations: Vec<NewNation> = loaded_nations
+/// Find the nation with the given Belfiore code in the database
+pub fn find_nation_by_code(conn: &mut DbConnection, code: &str) -> Option<Nation> {
+    nations
+        .filter(nation_code.eq(code))
+        .select(Nation::as_select())
+        .first(conn)
+        .optional()
+        .expect("Error loading nation")
+}
+
+/// Iterate over all the italian cities in the database, loading one at a time
+pub fn iter_cities(
+    conn: &mut DbConnection,
+) -> Result<impl Iterator<Item = Result<City, DbError>> + '_, DbError> {
+    let rows = cities
+        .select(City::as_select())
+        .load_iter::<City, DefaultLoadingMode>(conn)?;
+    Ok(rows.map(|row| row.map_err(DbError::from)))
+}
+
+/// Iterate over all the nations in the database, loading one at a time
+pub fn iter_nations(
+    conn: &mut DbConnection,
+) -> Result<impl Iterator<Item = Result<Nation, DbError>> + '_, DbError> {
+    let rows = nations
+        .select(Nation::as_select())
+        .load_iter::<Nation, DefaultLoadingMode>(conn)?;
+    Ok(rows.map(|row| row.map_err(DbError::from)))
+}
+
+/// Load all the italian cities in the database, sorted by name
+pub fn all_cities(conn: &mut DbConnection) -> Result<Vec<City>, DbError> {
+    Ok(cities
+        .order((city_name, city_code))
+        .select(City::as_select())
+        .load(conn)?)
+}
+
+/// Load the italian cities which are the capital of their province, sorted by name, like the ones to offer in a short list of the major cities
+pub fn list_province_capitals(conn: &mut DbConnection) -> Result<Vec<City>, DbError> {
+    Ok(cities
+        .filter(is_province.eq(true))
+        .order((city_name, city_code))
+        .select(City::as_select())
+        .load(conn)?)
+}
+
+/// Load all the nations in the database, sorted by name
+pub fn all_nations(conn: &mut DbConnection) -> Result<Vec<Nation>, DbError> {
+    Ok(nations
+        .order((nation_name, nation_code))
+        .select(Nation::as_select())
+        .load(conn)?)
+}
+
+/// Load at most `limit` italian cities sorted by name, skipping the first `offset`, to fetch them one page at a time
+pub fn list_cities(conn: &mut DbConnection, offset: i64, limit: i64) -> Result<Vec<City>, DbError> {
+    Ok(cities
+        .order((city_name, city_code))
+        .offset(offset)
+        .limit(limit)
+        .select(City::as_select())
+        .load(conn)?)
+}
+
+/// Load at most `limit` nations sorted by name, skipping the first `offset`, to fetch them one page at a time
+pub fn list_nations(
+    conn: &mut DbConnection,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<Nation>, DbError> {
+    Ok(nations
+        .order((nation_name, nation_code))
+        .offset(offset)
+        .limit(limit)
+        .select(Nation::as_select())
+        .load(conn)?)
+}
+
+/// The counts of [PopulateStats] recorded in the database by the last [populate_db], since the rows skipped can't be counted afterwards.\
+/// The counts of a table whose file wasn't loaded are the ones of the previous population.
+#[derive(Queryable, Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct PopulateCounts {
+    /// Nations without a code in the nations file, which all got the `0000` code so that only one of them was kept
+    pub nations_without_code: i64,
+    /// Nations skipped because their code was already used by a previous one
+    pub skipped_nations: i64,
+    /// Cities skipped because their code was already used by a previous one
+    pub skipped_cities: i64,
+}
+
+/// Summary of the content of the database, as reported by [db_info]
+pub struct DbInfo {
+    pub cities: i64,
+    pub nations: i64,
+    /// The counts recorded by the last [populate_db], `None` if it hasn't been run since they are recorded or the database is outdated
+    pub populate_counts: Option<PopulateCounts>,
+    /// Codes used by both a city and a nation, sorted
+    pub duplicate_codes: Vec<String>,
+    /// Whether all the embedded migrations have been applied
+    pub migrations_applied: bool,
+}
+
+/// Count the rows of the database and look for the codes used by both a city and a nation.\
+/// It works on outdated databases too, reporting them with [DbInfo::migrations_applied].
+///
+/// # Examples
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-info-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let nations_file = dir.join("nations.json");
+/// let cities_file = dir.join("cities.json");
+/// fs::write(
+///     &nations_file,
+///     r#"[{"name": "ITALIA", "code": ""}, {"name": "ANTARTIDE", "code": ""}, {"name": "ATLANTIDE", "code": ""}, {"name": "GERMANIA", "code": "Z112"}]"#,
+/// )
+/// .unwrap();
+/// fs::write(&cities_file, r#"[{"name": "Roma", "code": "H501"}, {"name": "Roma", "code": "H501"}]"#).unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// assert_eq!(db_info(&mut conn).unwrap().populate_counts, None);
+/// let mut populate = |nations_path, cities_path| {
+///     populate_db_with_connection(
+///         &mut conn,
+///         nations_path,
+///         cities_path,
+///         DataFormat::Minimal,
+///         PopulateMode::default(),
+///         DuplicateStrategy::default(),
+///         InvalidCodeStrategy::default(),
+///     )
+///     .unwrap()
+/// };
+/// assert_eq!(populate(Some(&nations_file), None).nations_without_code, 3);
+/// // Only one nation with the 0000 code is left, but the collapsed ones are still counted
+/// populate(None, Some(&cities_file));
+/// fs::remove_dir_all(&dir).unwrap();
+/// let info = db_info(&mut conn).unwrap();
+/// assert_eq!((info.nations, info.cities), (2, 1));
+/// let counts = info.populate_counts.unwrap();
+/// assert_eq!(
+///     (counts.nations_without_code, counts.skipped_nations, counts.skipped_cities),
+///     (3, 2, 1)
+/// );
+/// ```
+pub fn db_info(conn: &mut DbConnection) -> Result<DbInfo, DbError> {
+    let migrations_applied = match check_migrations_applied(conn) {
+        Ok(()) => true,
+        Err(DbError::PendingMigrations) => false,
+        Err(e) => return Err(e),
+    };
+    let city_codes: Vec<String> = cities.select(city_code).load(conn)?;
+    let nation_codes: Vec<String> = nations.select(nation_code).load(conn)?;
+    let populate_counts = if migrations_applied {
+        load_populate_counts(conn)?
+    } else {
+        None
+    };
+    let (cities_count, nations_count) = (city_codes.len() as i64, nation_codes.len() as i64);
+    let mut seen_codes = HashSet::new();
+    let mut duplicate_codes: Vec<String> = city_codes
         .into_iter()
-        .map(|n| {
-            if n.nation_code.is_empty() {
-                NewNation {
-                    nation_code: "0000".to_string(),
-                    ..n
-                }
-            } else {
-                n
+        .chain(nation_codes)
+        .filter(|c| !seen_codes.insert(c.clone()))
+        .collect();
+    duplicate_codes.sort();
+    duplicate_codes.dedup();
+    Ok(DbInfo {
+        cities: cities_count,
+        nations: nations_count,
+        populate_counts,
+        duplicate_codes,
+        migrations_applied,
+    })
+}
+
+/// Populate the database using the data in the given nations and cities files, in a single transaction.\
+/// A table whose file is `None` is left untouched, so the nations and the cities can be refreshed independently; the migrations are run anyway.\
+/// Rows with a code already in the database are replaced, or the tables are emptied first if `mode` is [PopulateMode::Truncate].\
+/// The rows are inserted in batches, so files of any size stay within the limit of values bound to a query.\
+/// It also fixes some nation codes incompatibility, then handles the nations with the same code as stated by `duplicates` and the rows with an invalid code as stated by `invalid_codes`.\
+/// The database is the one at the given url, the path of the file with SQLite; use [populate_db_with_connection] to populate an already open connection.\
+/// These files, `gi_nazioni.json` and `gi_comuni.json`, can be obtained [here](https://www.gardainformatica.it/database-comuni-italiani).
+///
+/// # Examples
+/// A bad cities file leaves the database untouched, without loading the nations either:
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-transaction-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let database_url = dir.join("data.db").to_str().unwrap().to_string();
+/// let nations_file = dir.join("gi_nazioni.json");
+/// let cities_file = dir.join("gi_comuni.json");
+/// fs::write(&nations_file, r#"[{"sigla_nazione": "DE", "codice_belfiore": "Z112", "denominazione_nazione": "GERMANIA", "denominazione_cittadinanza": "TEDESCA"}]"#).unwrap();
+/// fs::write(&cities_file, "[{").unwrap();
+///
+/// let result = populate_db(&database_url, Some(&nations_file), Some(&cities_file), PopulateMode::default(), DuplicateStrategy::default(), InvalidCodeStrategy::default());
+/// assert!(matches!(result, Err(DbError::InvalidJson { .. })));
+/// let mut conn = establish_connection(&database_url);
+/// assert!(all_nations(&mut conn).unwrap_or_default().is_empty());
+///
+/// // The nations already loaded are kept, even when the tables should have been emptied first
+/// populate_db(&database_url, Some(&nations_file), None, PopulateMode::default(), DuplicateStrategy::default(), InvalidCodeStrategy::default()).unwrap();
+/// let result = populate_db(&database_url, Some(&nations_file), Some(&cities_file), PopulateMode::Truncate, DuplicateStrategy::default(), InvalidCodeStrategy::default());
+/// assert!(result.is_err());
+/// assert_eq!(all_nations(&mut conn).unwrap()[0].nation_code, "Z112");
+/// fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn populate_db(
+    database_url: &str,
+    nations_path: Option<&Path>,
+    cities_path: Option<&Path>,
+    mode: PopulateMode,
+    duplicates: DuplicateStrategy,
+    invalid_codes: InvalidCodeStrategy,
+) -> Result<PopulateStats, DbError> {
+    populate_db_with_format(
+        database_url,
+        nations_path,
+        cities_path,
+        DataFormat::GardaInformatica,
+        mode,
+        duplicates,
+        invalid_codes,
+    )
+}
+
+/// Populate the database at the given url as in [populate_db], reading the nations and cities files in the given format
+pub fn populate_db_with_format(
+    database_url: &str,
+    nations_path: Option<&Path>,
+    cities_path: Option<&Path>,
+    format: DataFormat,
+    mode: PopulateMode,
+    duplicates: DuplicateStrategy,
+    invalid_codes: InvalidCodeStrategy,
+) -> Result<PopulateStats, DbError> {
+    let mut conn = DbConnection::establish(database_url).map_err(|_| DbError::NotExist)?;
+    populate_db_with_connection(
+        &mut conn,
+        nations_path,
+        cities_path,
+        format,
+        mode,
+        duplicates,
+        invalid_codes,
+    )
+}
+
+/// Populate the database of the given connection as in [populate_db_with_format]
+///
+/// # Examples
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-rejected-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let cities_file = dir.join("cities.json");
+/// fs::write(
+///     &cities_file,
+///     r#"[{"name": "Roma", "code": "H501"}, {"name": "Milano", "code": "F2O5"}, {"name": "Nessuno", "code": "0000"}]"#,
+/// )
+/// .unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// let populate = |conn: &mut _, invalid_codes| {
+///     populate_db_with_connection(
+///         conn,
+///         None,
+///         Some(&cities_file),
+///         DataFormat::Minimal,
+///         PopulateMode::default(),
+///         DuplicateStrategy::default(),
+///         invalid_codes,
+///     )
+/// };
+/// let error = populate(&mut conn, InvalidCodeStrategy::Error).err().unwrap();
+/// assert_eq!(error.to_string(), "Milano has the invalid code F2O5");
+/// assert!(find_city_by_code(&mut conn, "H501").is_none());
+///
+/// let stats = populate(&mut conn, InvalidCodeStrategy::Skip).unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+/// assert_eq!(stats.cities, 1);
+/// assert_eq!(stats.rejected_cities[0].city_code, "F2O5");
+/// assert_eq!(stats.rejected_cities[1].city_name, "Nessuno");
+/// assert!(find_city_by_code(&mut conn, "H501").is_some());
+/// ```
+///
+/// The cities sharing the same code are handled as stated by `duplicates`, like the nations:
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-duplicates-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let cities_file = dir.join("cities.json");
+/// fs::write(
+///     &cities_file,
+///     r#"[{"name": "Roma", "code": "H501"}, {"name": "Milano", "code": "F205"}, {"name": "Roma Capitale", "code": "H501"}]"#,
+/// )
+/// .unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// let populate = |conn: &mut _, duplicates| {
+///     populate_db_with_connection(
+///         conn,
+///         None,
+///         Some(&cities_file),
+///         DataFormat::Minimal,
+///         PopulateMode::default(),
+///         duplicates,
+///         InvalidCodeStrategy::default(),
+///     )
+/// };
+/// let error = populate(&mut conn, DuplicateStrategy::Error).err().unwrap();
+/// assert_eq!(error.to_string(), "More cities have the code H501");
+/// assert!(find_city_by_code(&mut conn, "F205").is_none());
+///
+/// let stats = populate(&mut conn, DuplicateStrategy::Skip).unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+/// assert_eq!((stats.cities, stats.skipped_cities), (2, 1));
+/// assert_eq!(find_city_by_code(&mut conn, "H501").unwrap().city_name, "Roma");
+/// ```
+///
+/// The nations without a code all get the `0000` code, and only ITALIA is kept among them, wherever it is listed:
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-italy-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let nations_file = dir.join("nations.json");
+/// fs::write(
+///     &nations_file,
+///     r#"[{"name": "TERRITORI NON CODIFICATI", "code": ""}, {"name": "ITALIA", "code": ""}, {"name": "GERMANIA", "code": "Z112"}]"#,
+/// )
+/// .unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// let stats = populate_db_with_connection(
+///     &mut conn,
+///     Some(&nations_file),
+///     None,
+///     DataFormat::Minimal,
+///     PopulateMode::default(),
+///     DuplicateStrategy::Skip,
+///     InvalidCodeStrategy::default(),
+/// )
+/// .unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+/// assert_eq!((stats.nations, stats.skipped_nations), (2, 1));
+/// assert_eq!(search_nation(&mut conn, "italia", None)[0].nation_code, "0000");
+/// assert!(search_nation(&mut conn, "territori non codificati", None).is_empty());
+/// ```
+pub fn populate_db_with_connection(
+    conn: &mut DbConnection,
+    nations_path: Option<&Path>,
+    cities_path: Option<&Path>,
+    format: DataFormat,
+    mode: PopulateMode,
+    duplicates: DuplicateStrategy,
+    invalid_codes: InvalidCodeStrategy,
+) -> Result<PopulateStats, DbError> {
+    let loaded_nations: Option<Vec<NewNation>> = match nations_path {
+        Some(path) => Some(match format {
+            DataFormat::GardaInformatica => read_rows::<NewLoadedNation, _>(path)?,
+            DataFormat::Minimal => read_rows::<MinimalLoadedNation, _>(path)?,
+        }),
+        None => None,
+    };
+    let loaded_cities: Option<Vec<NewCity>> = match cities_path {
+        Some(path) => Some(match format {
+            DataFormat::GardaInformatica => read_rows::<NewLoadedCity, _>(path)?,
+            DataFormat::Minimal => read_rows::<MinimalLoadedCity, _>(path)?,
+        }),
+        None => None,
+    };
+    let (loaded_cities, skipped_cities, rejected_cities) = match loaded_cities {
+        Some(loaded_cities) => {
+            let (loaded_cities, rejected_cities) = reject_invalid_codes(
+                loaded_cities,
+                |c| (c.city_name.as_str(), c.city_code.as_str()),
+                is_belfiore_code,
+                invalid_codes,
+            )?;
+            // A repeated code would make the upsert of PostgreSQL fail, as it can't update a row twice
+            let (loaded_cities, skipped_cities) = dedup_by_code(
+                loaded_cities,
+                |c| &c.city_code,
+                |_| false,
+                duplicates,
+                DbError::DuplicateCityCode,
+            )?;
+            (Some(loaded_cities), skipped_cities, rejected_cities)
+        }
+        None => (None, 0, Vec::new()),
+    };
+    let nations_without_code = loaded_nations.as_ref().map_or(0, |rows| {
+        rows.iter().filter(|n| n.nation_code.is_empty()).count()
+    });
+    let (loaded_nations, skipped_nations, rejected_nations) = match loaded_nations {
+        Some(loaded_nations) => {
+            let loaded_nations: Vec<NewNation> = loaded_nations
+                .into_iter()
+                .map(|n| {
+                    if n.nation_code.is_empty() {
+                        NewNation {
+                            nation_code: "0000".to_string(),
+                            ..n
+                        }
+                    } else {
+                        n
+                    }
+                })
+                .collect();
+            let (loaded_nations, rejected_nations) = reject_invalid_codes(
+                loaded_nations,
+                |n| (n.nation_name.as_str(), n.nation_code.as_str()),
+                // Only the nations can have the code given to the ones without it, like Italy
+                |code| code == "0000" || is_belfiore_code(code),
+                invalid_codes,
+            )?;
+            // Italy is kept among the nations without a code, so it can still be found by name
+            let (loaded_nations, skipped_nations) = dedup_by_code(
+                loaded_nations,
+                |n| &n.nation_code,
+                |n| n.nation_code == "0000" && n.nation_name.trim().eq_ignore_ascii_case("ITALIA"),
+                duplicates,
+                DbError::DuplicateNationCode,
+            )?;
+            (Some(loaded_nations), skipped_nations, rejected_nations)
+        }
+        None => (None, 0, Vec::new()),
+    };
+    for n in &rejected_nations {
+        log!(
+            warn,
+            "Skipped the nation {} with the invalid code {}",
+            n.nation_name,
+            n.nation_code
+        );
+    }
+    for c in &rejected_cities {
+        log!(
+            warn,
+            "Skipped the city {} with the invalid code {}",
+            c.city_name,
+            c.city_code
+        );
+    }
+    if skipped_nations > 0 {
+        log!(
+            warn,
+            "Skipped {skipped_nations} nations with an already used code"
+        );
+    }
+    if skipped_cities > 0 {
+        log!(
+            warn,
+            "Skipped {skipped_cities} cities with an already used code"
+        );
+    }
+    if let (Some(path), Some(rows)) = (nations_path, &loaded_nations) {
+        log!(
+            debug,
+            "Loaded {} nations from {}",
+            rows.len(),
+            path.display()
+        );
+    }
+    if let (Some(path), Some(rows)) = (cities_path, &loaded_cities) {
+        log!(
+            debug,
+            "Loaded {} cities from {}",
+            rows.len(),
+            path.display()
+        );
+    }
+    let start = Instant::now();
+    // Migrations and inserts share a single transaction, so a failure leaves the database untouched
+    let stats = conn.transaction(|conn| {
+        run_migrations(conn)?;
+        let previous_counts = load_populate_counts(conn)?.unwrap_or_default();
+        let counts = PopulateCounts {
+            nations_without_code: match loaded_nations {
+                Some(_) => nations_without_code as i64,
+                None => previous_counts.nations_without_code,
+            },
+            skipped_nations: match loaded_nations {
+                Some(_) => skipped_nations as i64,
+                None => previous_counts.skipped_nations,
+            },
+            skipped_cities: match loaded_cities {
+                Some(_) => skipped_cities as i64,
+                None => previous_counts.skipped_cities,
+            },
+        };
+        store_populate_counts(conn, counts)?;
+        let mut inserted_nations = 0;
+        if let Some(rows) = loaded_nations {
+            if mode == PopulateMode::Truncate {
+                diesel::delete(nations).execute(conn)?;
+            }
+            inserted_nations = insert_in_batches(&rows, NATION_COLUMNS, "nations", |batch| {
+                upsert_nations(conn, batch)
+            })?;
+        }
+        let mut inserted_cities = 0;
+        if let Some(rows) = loaded_cities {
+            if mode == PopulateMode::Truncate {
+                diesel::delete(cities).execute(conn)?;
             }
+            inserted_cities = insert_in_batches(&rows, CITY_COLUMNS, "cities", |batch| {
+                upsert_cities(conn, batch)
+            })?;
+        }
+        Ok(PopulateStats {
+            nations: inserted_nations,
+            cities: inserted_cities,
+            nations_without_code,
+            skipped_nations,
+            skipped_cities,
+            rejected_nations,
+            rejected_cities,
         })
-        .collect();
+    });
+    match &stats {
+        Ok(stats) => log!(
+            info,
+            "Inserted {} nations and {} cities in {:?}",
+            stats.nations,
+            stats.cities,
+            start.elapsed()
+        ),
+        Err(e) => log!(error, "Can't populate the database: {e}"),
+    }
+    stats
+}
+
+/// Load the counts recorded by the last population, if any
+fn load_populate_counts(conn: &mut DbConnection) -> QueryResult<Option<PopulateCounts>> {
+    use crate::schema::populate_stats;
+    populate_stats::table
+        .select((
+            populate_stats::nations_without_code,
+            populate_stats::skipped_nations,
+            populate_stats::skipped_cities,
+        ))
+        .first(conn)
+        .optional()
+}
+
+/// Record the given counts, replacing the ones of the previous population
+fn store_populate_counts(conn: &mut DbConnection, counts: PopulateCounts) -> QueryResult<()> {
+    use crate::schema::populate_stats;
+    diesel::delete(populate_stats::table).execute(conn)?;
+    diesel::insert_into(populate_stats::table)
+        .values((
+            populate_stats::id.eq(1),
+            populate_stats::nations_without_code.eq(counts.nations_without_code),
+            populate_stats::skipped_nations.eq(counts.skipped_nations),
+            populate_stats::skipped_cities.eq(counts.skipped_cities),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Split the rows into the ones whose code is accepted by `is_valid_code` and the ones to reject, or fail on the first one to reject if `invalid_codes` is [InvalidCodeStrategy::Error]
+fn reject_invalid_codes<T>(
+    rows: Vec<T>,
+    name_and_code: impl Fn(&T) -> (&str, &str),
+    is_valid_code: impl Fn(&str) -> bool,
+    invalid_codes: InvalidCodeStrategy,
+) -> Result<(Vec<T>, Vec<T>), DbError> {
+    let (valid_rows, rejected_rows): (Vec<T>, Vec<T>) = rows
+        .into_iter()
+        .partition(|row| is_valid_code(name_and_code(row).1));
+    if let (InvalidCodeStrategy::Error, Some(row)) = (invalid_codes, rejected_rows.first()) {
+        let (name, code) = name_and_code(row);
+        return Err(DbError::InvalidLocationCode {
+            name: name.to_string(),
+            code: code.to_string(),
+        });
+    }
+    Ok((valid_rows, rejected_rows))
+}
+
+/// Keep only the first row with each code, unless a following one is `preferred` over it, returning the rows kept and the number of the skipped ones.\
+/// It fails with `duplicate_error` on the first repeated code if `duplicates` is [DuplicateStrategy::Error].
+fn dedup_by_code<T>(
+    rows: Vec<T>,
+    code: impl Fn(&T) -> &String,
+    preferred: impl Fn(&T) -> bool,
+    duplicates: DuplicateStrategy,
+    duplicate_error: fn(String) -> DbError,
+) -> Result<(Vec<T>, usize), DbError> {
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut unique_rows: Vec<T> = Vec::with_capacity(rows.len());
+    let mut skipped = 0;
+    for row in rows {
+        if let Some(&position) = positions.get(code(&row)) {
+            if duplicates == DuplicateStrategy::Error {
+                return Err(duplicate_error(code(&row).clone()));
+            }
+            if preferred(&row) && !preferred(&unique_rows[position]) {
+                unique_rows[position] = row;
+            }
+            skipped += 1;
+        } else {
+            positions.insert(code(&row).clone(), unique_rows.len());
+            unique_rows.push(row);
+        }
+    }
+    Ok((unique_rows, skipped))
+}
+
+/// Highest number of values bound to a single query, the default limit of SQLite
+const MAX_BIND_VARIABLES: usize = 999;
+/// Number of columns of a [NewNation], which binds a value for each one
+const NATION_COLUMNS: usize = 3;
+/// Number of columns of a [NewCity], which binds a value for each one
+const CITY_COLUMNS: usize = 8;
+
+/// Insert the rows with `insert` in batches small enough to stay within [MAX_BIND_VARIABLES], returning how many were inserted
+fn insert_in_batches<T>(
+    rows: &[T],
+    columns: usize,
+    table_name: &str,
+    mut insert: impl FnMut(&[T]) -> QueryResult<usize>,
+) -> QueryResult<usize> {
+    let mut inserted = 0;
+    for batch in rows.chunks(MAX_BIND_VARIABLES / columns) {
+        inserted += insert(batch)?;
+        log!(debug, "Inserted {inserted} of {} {table_name}", rows.len());
+    }
+    Ok(inserted)
+}
+
+/// Insert the nations, replacing the ones with the same code
+#[cfg(feature = "sqlite")]
+fn upsert_nations(conn: &mut DbConnection, rows: &[NewNation]) -> QueryResult<usize> {
+    diesel::replace_into(nations).values(rows).execute(conn)
+}
+
+/// Insert the nations, replacing the ones with the same code
+#[cfg(feature = "postgres")]
+fn upsert_nations(conn: &mut DbConnection, rows: &[NewNation]) -> QueryResult<usize> {
     diesel::insert_into(nations)
-        .values(loaded_nations)
-        .execute(&mut conn)
-        .expect("Error during nation table population");
-    let cities_file_content =
-        fs::read_to_string("gi_comuni.json").expect("Error in cities json file opening");
-    let loaded_cities: Vec<NewLoadedCity> = serde_json::from_str(&cities_file_content).unwrap();
-    let loaded_cities: Vec<NewCity> = loaded_cities.into_iter().map(NewCity::from).collect();
+        .values(rows)
+        .on_conflict(nation_code)
+        .do_update()
+        .set((nation_name.eq(excluded(nation_name)), iso.eq(excluded(iso))))
+        .execute(conn)
+}
+
+/// Insert the cities, replacing the ones with the same code
+#[cfg(feature = "sqlite")]
+fn upsert_cities(conn: &mut DbConnection, rows: &[NewCity]) -> QueryResult<usize> {
+    diesel::replace_into(cities).values(rows).execute(conn)
+}
+
+/// Insert the cities, replacing the ones with the same code
+#[cfg(feature = "postgres")]
+fn upsert_cities(conn: &mut DbConnection, rows: &[NewCity]) -> QueryResult<usize> {
     diesel::insert_into(cities)
-        .values(loaded_cities)
-        .execute(&mut conn)
-        .expect("Error during cities table population");
+        .values(rows)
+        .on_conflict(city_code)
+        .do_update()
+        .set((
+            city_name.eq(excluded(city_name)),
+            province.eq(excluded(province)),
+            is_province.eq(excluded(is_province)),
+            lat.eq(excluded(lat)),
+            lon.eq(excluded(lon)),
+            alternative_name.eq(excluded(alternative_name)),
+            valid_until.eq(excluded(valid_until)),
+        ))
+        .execute(conn)
+}
+
+/// Read the rows of the given json file and convert them to the rows to insert
+fn read_rows<T: DeserializeOwned, R: From<T>>(path: &Path) -> Result<Vec<R>, DbError> {
+    let rows: Vec<T> = read_json_file(path)?;
+    Ok(rows.into_iter().map(R::from).collect())
+}
 
-    println!("Database successfully populated!");
+fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<T, DbError> {
+    let content = fs::read_to_string(path).map_err(|source| DbError::FileRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&content).map_err(|source| DbError::InvalidJson {
+        path: path.to_path_buf(),
+        source,
+    })
 }
@@ -9,79 +9,106 @@
 //! A library with useful functions to calculate a person's fiscal code, the italian equivalent of the social security number.
 //! ## Usage
 //! To use this library, a database populated with all the italian cities and all the nations is needed.\
-//! To build one, download `gi_comuni.json` and `gi_nazioni.json` from [here](https://www.gardainformatica.it/database-comuni-italiani), place them in the root directory of the project and call [db_utils::populate_db]
-use chrono::{Datelike, Month, NaiveDate};
+//! To build one, download `gi_comuni.json` and `gi_nazioni.json` from [here](https://www.gardainformatica.it/database-comuni-italiani) and pass the url of the database and their paths to [db_utils::populate_db]
+//!
+//! The database is a SQLite file with the default `sqlite` feature, or a PostgreSQL database with the `postgres` feature, which needs the default features disabled.
+//!
+//! Alternatively, with the `bundled-data` feature the Belfiore codes of the same files are compiled into the library and can be found with the functions in `bundled`, without any database.\
+//! At build time, the files are searched in the root directory of the package or at the paths in the `CODICEFISCALE_NATIONS_FILE` and `CODICEFISCALE_CITIES_FILE` environment variables.
+//!
+//! With the `log` feature the searches, the population of the database and the generation of the codes emit records with the [log](https://docs.rs/log) crate, shown only if a logger is initialized.
+//!
+//! The functions in [algorithm] only use `core` and take primitive inputs, so they can be used where the rest of the library can't, like embedded or WASM targets.
+use chrono::{Datelike, Local, Month, NaiveDate};
 
 use clap::ValueEnum;
-use lazy_static::lazy_static;
 use models::{City, Nation};
-use std::collections::HashMap;
+use resolver::LocationResolver;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use thiserror::Error;
 
+/// Emit a record with the macro of the `log` crate for the given level, only if the `log` feature is enabled
+macro_rules! log {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+/// Pure algorithm of the code using only `core`, without any allocation, database or date library
+pub mod algorithm;
+/// Generation of the codes of all the people in a CSV file
+pub mod batch;
+/// Belfiore codes compiled into the library, to find nations and cities without a database
+#[cfg(feature = "bundled-data")]
+pub mod bundled;
 /// Functions to search and populate the database
+#[cfg(feature = "db")]
 pub mod db_utils;
 /// Representations of nations and cities in the database
 pub mod models;
+/// Functions to decode and validate existing codes
+pub mod parser;
+/// Abstraction over the sources of the Belfiore codes
+pub mod resolver;
+#[cfg(feature = "db")]
 pub(crate) mod schema;
 mod utils;
+/// Bindings for JavaScript of the functions not needing any database
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Earliest birth year accepted by default during the code generation
+pub const DEFAULT_MIN_BIRTH_YEAR: i32 = 1900;
 
-lazy_static! {
-    static ref ALPHABET_VEC: Vec<char> = vec![
-        'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R',
-        'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-    ];
-    static ref ODD_LOOKUP_TABLE: HashMap<char, u32> = HashMap::from([
-        ('0', 1),
-        ('1', 0),
-        ('2', 5),
-        ('3', 7),
-        ('4', 9),
-        ('5', 13),
-        ('6', 15),
-        ('7', 17),
-        ('8', 19),
-        ('9', 21),
-        ('A', 1),
-        ('B', 0),
-        ('C', 5),
-        ('D', 7),
-        ('E', 9),
-        ('F', 13),
-        ('G', 15),
-        ('H', 17),
-        ('I', 19),
-        ('J', 21),
-        ('K', 2),
-        ('L', 4),
-        ('M', 18),
-        ('N', 20),
-        ('O', 11),
-        ('P', 3),
-        ('Q', 6),
-        ('R', 8),
-        ('S', 12),
-        ('T', 14),
-        ('U', 16),
-        ('V', 10),
-        ('W', 22),
-        ('X', 25),
-        ('Y', 24),
-        ('Z', 23),
-    ]);
-    static ref HOMOCODIC_LOOKUP_TABLE: HashMap<u32, char> = HashMap::from([
-        (0, 'L'),
-        (1, 'M'),
-        (2, 'N'),
-        (3, 'P'),
-        (4, 'Q'),
-        (5, 'R'),
-        (6, 'S'),
-        (7, 'T'),
-        (8, 'U'),
-        (9, 'V'),
-    ]);
-}
-
-/// Generate the code with the given data
+/// Generate the code with the given data.\
+/// The city is used only if the nation is Italy, and a city with the `0000` code of Italy is rejected with [CodeError::InvalidLocationCode].
+///
+/// # Examples
+/// Names and surnames with less than three letters are padded with `X`, after their consonants and vowels:
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::models::{City, Nation};
+/// use codicefiscale::{generate_code, Sex};
+///
+/// let letters = |name: &str, surname: &str| {
+///     let italy = Nation {
+///         id: 1,
+///         nation_name: "ITALIA".to_string(),
+///         nation_code: "0000".to_string(),
+///         iso: "IT".to_string(),
+///     };
+///     let rome = City {
+///         id: 1,
+///         city_name: "ROMA".to_string(),
+///         city_code: "H501".to_string(),
+///         province: "RM".to_string(),
+///         is_province: true,
+///         lat: Some(41.89),
+///         lon: Some(12.48),
+///         alternative_name: None,
+///         valid_until: None,
+///     };
+///     let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+///     let code = generate_code(name.to_string(), surname.to_string(), Sex::M, italy, rome, birth_date)
+///         .unwrap();
+///     code[..6].to_string()
+/// };
+/// assert_eq!(letters("O", "O"), "OXXOXX");
+/// assert_eq!(letters("B", "Ai"), "AIXBXX");
+/// assert_eq!(letters("Io", "Re"), "REXIOX");
+/// assert_eq!(letters("Bo", "Rossi"), "RSSBOX");
+/// assert_eq!(letters("Ly", "Rossi"), "RSSLYX");
+/// // Up to three consonants the vowels follow them, from four consonants on the 1st, 3rd and 4th are taken
+/// assert_eq!(letters("Ida", "Rossi"), "RSSDIA");
+/// assert_eq!(letters("Anna", "Rossi"), "RSSNNA");
+/// assert_eq!(letters("Bea", "Rossi"), "RSSBEA");
+/// assert_eq!(letters("Mario", "Rossi"), "RSSMRA");
+/// assert_eq!(letters("Giovanni", "Rossi"), "RSSGNN");
+/// ```
 pub fn generate_code(
     name: String,
     surname: String,
@@ -89,58 +116,1136 @@ pub fn generate_code(
     birth_nation: Nation,
     birth_city: City,
     birth_date: NaiveDate,
-) -> String {
-    let name_code = extract_name_letters(&name);
-    let surname_code = extract_surname_letters(&surname);
-    let year_code = get_year(&birth_date.year().to_string());
-    let born_month = Month::try_from(birth_date.month() as u8).unwrap();
-    let month_code = get_month_letter(&born_month).to_string();
-    let day_code = get_day(birth_date.day(), sex);
+) -> Result<String, CodeError> {
     let location_code = if birth_nation.nation_code == "0000" {
         birth_city.city_code
     } else {
         birth_nation.nation_code
     };
-    let preliminary_code =
-        format!("{surname_code}{name_code}{year_code}{month_code}{day_code:0>2}{location_code}");
-    let check_code = get_control_character(&preliminary_code);
-    format!("{preliminary_code}{check_code}")
+    assemble_code(
+        &name,
+        &surname,
+        sex,
+        &location_code,
+        birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// Generate the code as in [generate_code], taking the birth date as separate year, month and day.\
+/// Returns [CodeError::InvalidDate] if they don't form a valid date, like February 30.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::models::{City, Nation};
+/// use codicefiscale::{generate_code_ymd, CodeError, Sex};
+///
+/// let nation = || Nation {
+///     id: 1,
+///     nation_name: "ITALIA".to_string(),
+///     nation_code: "0000".to_string(),
+///     iso: "IT".to_string(),
+/// };
+/// let city = || City {
+///     id: 1,
+///     city_name: "Roma".to_string(),
+///     city_code: "H501".to_string(),
+///     province: "RM".to_string(),
+///     is_province: true,
+///     lat: None,
+///     lon: None,
+///     alternative_name: None,
+///     valid_until: None,
+/// };
+/// let code = |year, month, day| {
+///     generate_code_ymd("Mario".to_string(), "Rossi".to_string(), Sex::M, nation(), city(), year, month, day)
+/// };
+/// assert_eq!(code(1980, 1, 1).unwrap(), "RSSMRA80A01H501U");
+/// assert!(matches!(code(1980, 2, 30), Err(CodeError::InvalidDate { year: 1980, month: 2, day: 30 })));
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn generate_code_ymd(
+    name: String,
+    surname: String,
+    sex: Sex,
+    birth_nation: Nation,
+    birth_city: City,
+    year: i32,
+    month: u32,
+    day: u32,
+) -> Result<String, CodeError> {
+    let birth_date = NaiveDate::from_ymd_opt(year, month, day).ok_or(CodeError::InvalidDate {
+        year,
+        month,
+        day,
+    })?;
+    generate_code(name, surname, sex, birth_nation, birth_city, birth_date)
+}
+
+/// Generate the code of a person born outside Italy, which only needs the birth nation.\
+/// Returns [CodeError::BornInItaly] if the nation is Italy, since the code would need the birth city.
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::models::Nation;
+/// use codicefiscale::{generate_code_abroad, CodeError, Sex};
+///
+/// let nation = |nation_name: &str, nation_code: &str| Nation {
+///     id: 1,
+///     nation_name: nation_name.to_string(),
+///     nation_code: nation_code.to_string(),
+///     iso: String::new(),
+/// };
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// let code = generate_code_abroad("Mario", "Rossi", Sex::M, nation("GERMANIA", "Z112"), birth_date);
+/// assert_eq!(code.unwrap(), "RSSMRA80A01Z112F");
+/// let code = generate_code_abroad("Mario", "Rossi", Sex::M, nation("ITALIA", "0000"), birth_date);
+/// assert!(matches!(code, Err(CodeError::BornInItaly)));
+/// ```
+pub fn generate_code_abroad(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    birth_nation: Nation,
+    birth_date: NaiveDate,
+) -> Result<String, CodeError> {
+    if birth_nation.nation_code == "0000" {
+        return Err(CodeError::BornInItaly);
+    }
+    assemble_code(
+        name,
+        surname,
+        sex,
+        &birth_nation.nation_code,
+        birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// Generate the code with the given data and the Belfiore code of the birth place, without any database
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::{generate_code_from_codes, Sex};
+///
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// let code = generate_code_from_codes("Mario", "Rossi", Sex::M, "H501", birth_date);
+/// assert_eq!(code.unwrap(), "RSSMRA80A01H501U");
+/// assert!(generate_code_from_codes("Mario", "Rossi", Sex::M, "H5O1", birth_date).is_err());
+/// ```
+///
+/// The `0000` code only marks Italy among the nations and is never a valid birth place:
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::{generate_code_from_codes, CodeError, Sex};
+///
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// let code = generate_code_from_codes("Mario", "Rossi", Sex::M, "0000", birth_date);
+/// assert!(matches!(code, Err(CodeError::InvalidLocationCode(_))));
+/// ```
+///
+/// Empty names are rejected before their letters are taken, while names made only of vowels are padded:
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::{generate_code_from_codes, CodeError, Sex};
+///
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// let code = |name: &str| generate_code_from_codes(name, "Rossi", Sex::F, "H501", birth_date);
+/// assert!(matches!(code(""), Err(CodeError::EmptyName)));
+/// assert!(matches!(code("   "), Err(CodeError::EmptyName)));
+/// assert_eq!(&code("Ea").unwrap()[3..6], "EAX");
+/// assert_eq!(&code("Aurea").unwrap()[3..6], "RAU");
+/// ```
+pub fn generate_code_from_codes(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    location_code: &str,
+    birth_date: NaiveDate,
+) -> Result<String, CodeError> {
+    assemble_code(
+        name,
+        surname,
+        sex,
+        location_code,
+        birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// Generate the code with the given data, finding the Belfiore code of the birth place with the given resolver.\
+/// The city is resolved only if the nation is Italy.
+pub fn generate_code_with_resolver(
+    resolver: &impl LocationResolver,
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    birth_nation: &str,
+    birth_city: &str,
+    birth_date: NaiveDate,
+) -> Result<String, CodeError> {
+    let nation_code = resolver.resolve_nation(birth_nation).ok_or_else(|| {
+        log!(debug, "Nation {birth_nation:?} not resolved");
+        CodeError::NationNotFound(birth_nation.to_string())
+    })?;
+    log!(trace, "Nation {birth_nation:?} resolved to {nation_code}");
+    let location_code = if nation_code == "0000" {
+        let city_code = resolver.resolve_city(birth_city).ok_or_else(|| {
+            log!(debug, "City {birth_city:?} not resolved");
+            CodeError::CityNotFound(birth_city.to_string())
+        })?;
+        log!(trace, "City {birth_city:?} resolved to {city_code}");
+        city_code
+    } else {
+        nation_code
+    };
+    assemble_code(
+        name,
+        surname,
+        sex,
+        &location_code,
+        birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// The data of a person needed to generate the code with a [LocationResolver]
+#[derive(Clone)]
+pub struct PersonRecord {
+    pub name: String,
+    pub surname: String,
+    pub sex: Sex,
+    /// The name of the birth nation, resolved with [LocationResolver::resolve_nation]
+    pub birth_nation: String,
+    /// The name of the birth city, resolved with [LocationResolver::resolve_cities] only if the nation is Italy
+    pub birth_city: String,
+    /// The initials of the province of the birth city, like `CO` for Como, to choose between the cities with the same name
+    pub birth_province: Option<String>,
+    pub birth_date: NaiveDate,
+}
+
+/// Lazily generate the codes of the given records, as in [generate_code_with_resolver].\
+/// The birth city is found among the ones with its name in the birth province, if given, and a name shared by more cities gives [CodeError::AmbiguousCity].\
+/// Every record is resolved and generated only when the next code is requested, so the records don't need to be kept in memory.\
+/// An error yields a [CodeError] for that record only, without stopping the iteration.
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::resolver::MapResolver;
+/// use codicefiscale::{generate_iter, CodeError, PersonRecord, Sex};
+///
+/// let resolver = MapResolver::new()
+///     .with_nation("Italia", "0000")
+///     .with_city("Roma", "H501");
+/// let person = |birth_city: &str| PersonRecord {
+///     name: "Mario".to_string(),
+///     surname: "Rossi".to_string(),
+///     sex: Sex::M,
+///     birth_nation: "Italia".to_string(),
+///     birth_city: birth_city.to_string(),
+///     birth_province: None,
+///     birth_date: NaiveDate::from_ymd_opt(1980, 1, 1).unwrap(),
+/// };
+/// let mut codes = generate_iter(&resolver, [person("Roma"), person("Atlantide")].into_iter());
+/// assert_eq!(codes.next().unwrap().unwrap(), "RSSMRA80A01H501U");
+/// assert!(matches!(codes.next().unwrap(), Err(CodeError::CityNotFound(_))));
+/// assert!(codes.next().is_none());
+/// ```
+///
+/// The province tells apart the cities with the same name, when the resolver knows it:
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::resolver::LocationResolver;
+/// use codicefiscale::{generate_iter, CodeError, PersonRecord, Sex};
+///
+/// struct PeglioResolver;
+///
+/// impl LocationResolver for PeglioResolver {
+///     fn resolve_city(&self, _name: &str) -> Option<String> {
+///         Some("G415".to_string())
+///     }
+///
+///     fn resolve_nation(&self, _name: &str) -> Option<String> {
+///         Some("0000".to_string())
+///     }
+///
+///     fn resolve_cities(&self, _name: &str, province: Option<&str>) -> Vec<String> {
+///         match province {
+///             Some("CO") => vec!["G415".to_string()],
+///             Some("PU") => vec!["G416".to_string()],
+///             Some(_) => Vec::new(),
+///             None => vec!["G415".to_string(), "G416".to_string()],
+///         }
+///     }
+/// }
+///
+/// let person = |birth_province: Option<&str>| PersonRecord {
+///     name: "Mario".to_string(),
+///     surname: "Rossi".to_string(),
+///     sex: Sex::M,
+///     birth_nation: "Italia".to_string(),
+///     birth_city: "Peglio".to_string(),
+///     birth_province: birth_province.map(str::to_string),
+///     birth_date: NaiveDate::from_ymd_opt(1980, 1, 1).unwrap(),
+/// };
+/// let people = [person(Some("PU")), person(None), person(Some("RM"))];
+/// let mut codes = generate_iter(&PeglioResolver, people.into_iter());
+/// assert_eq!(&codes.next().unwrap().unwrap()[11..15], "G416");
+/// assert!(matches!(codes.next().unwrap(), Err(CodeError::AmbiguousCity(_))));
+/// assert!(matches!(codes.next().unwrap(), Err(CodeError::CityNotFound(_))));
+/// ```
+pub fn generate_iter<'a, R, I>(
+    resolver: &'a R,
+    records: I,
+) -> impl Iterator<Item = Result<String, CodeError>> + 'a
+where
+    R: LocationResolver,
+    I: Iterator<Item = PersonRecord> + 'a,
+{
+    records.map(move |record| generate_person_code(resolver, &record))
+}
+
+/// Generate the code of the person as in [generate_iter]
+pub(crate) fn generate_person_code(
+    resolver: &impl LocationResolver,
+    person: &PersonRecord,
+) -> Result<String, CodeError> {
+    let birth_nation = &person.birth_nation;
+    let nation_code = resolver.resolve_nation(birth_nation).ok_or_else(|| {
+        log!(debug, "Nation {birth_nation:?} not resolved");
+        CodeError::NationNotFound(birth_nation.to_string())
+    })?;
+    let location_code = if nation_code == "0000" {
+        let birth_city = &person.birth_city;
+        let province = person
+            .birth_province
+            .as_deref()
+            .map(str::trim)
+            .filter(|p| !p.is_empty());
+        let mut city_codes = resolver.resolve_cities(birth_city, province);
+        if city_codes.len() > 1 {
+            return Err(CodeError::AmbiguousCity(birth_city.to_string()));
+        }
+        city_codes.pop().ok_or_else(|| {
+            log!(debug, "City {birth_city:?} not resolved");
+            CodeError::CityNotFound(birth_city.to_string())
+        })?
+    } else {
+        nation_code
+    };
+    assemble_code(
+        &person.name,
+        &person.surname,
+        person.sex,
+        &location_code,
+        person.birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// The birth place of a [Person]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Birthplace {
+    /// The name of an italian city
+    City(String),
+    /// The name of a foreign nation
+    Nation(String),
+    /// The Belfiore code of the city or the nation
+    Code(String),
+}
+
+/// A person whose code can be generated with a single call, as an alternative to the functions taking every field as an argument
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::resolver::MapResolver;
+/// use codicefiscale::{Birthplace, Person, Sex};
+///
+/// let resolver = MapResolver::new().with_city("Roma", "H501");
+/// let person = Person {
+///     name: "Mario".to_string(),
+///     surname: "Rossi".to_string(),
+///     sex: Sex::M,
+///     birth_date: NaiveDate::from_ymd_opt(1980, 1, 1).unwrap(),
+///     birthplace: Birthplace::City("Roma".to_string()),
+/// };
+/// assert_eq!(person.fiscal_code(&resolver).unwrap(), "RSSMRA80A01H501U");
+///
+/// let json = r#"{"name":"Mario","surname":"Rossi","sex":"M","birth_date":"1980-01-01","birthplace":{"Code":"H501"}}"#;
+/// let person: Person = serde_json::from_str(json).unwrap();
+/// assert_eq!(person.fiscal_code(&resolver).unwrap(), "RSSMRA80A01H501U");
+/// ```
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Person {
+    pub name: String,
+    pub surname: String,
+    pub sex: Sex,
+    pub birth_date: NaiveDate,
+    pub birthplace: Birthplace,
+}
+
+impl Person {
+    /// Generate the code of the person, finding the Belfiore code of the birth place with the given resolver if needed.\
+    /// Returns [CodeError::BornInItaly] if the birth place is a nation resolved to Italy, since the code would need the birth city.
+    pub fn fiscal_code(&self, resolver: &impl LocationResolver) -> Result<String, CodeError> {
+        let location_code = match &self.birthplace {
+            Birthplace::City(city) => resolver
+                .resolve_city(city)
+                .ok_or_else(|| CodeError::CityNotFound(city.to_string()))?,
+            Birthplace::Nation(nation) => {
+                let nation_code = resolver
+                    .resolve_nation(nation)
+                    .ok_or_else(|| CodeError::NationNotFound(nation.to_string()))?;
+                if nation_code == "0000" {
+                    return Err(CodeError::BornInItaly);
+                }
+                nation_code
+            }
+            Birthplace::Code(code) => code.to_string(),
+        };
+        assemble_code(
+            &self.name,
+            &self.surname,
+            self.sex,
+            &location_code,
+            self.birth_date,
+            DEFAULT_MIN_BIRTH_YEAR,
+            NameNormalization::default(),
+        )
+    }
+}
+
+/// Checks that the birth date is not in the future nor before the first day of `min_year`
+pub fn validate_birth_date(birth_date: NaiveDate, min_year: i32) -> Result<(), CodeError> {
+    if birth_date > Local::now().date_naive() {
+        return Err(CodeError::BirthDateInFuture(birth_date));
+    }
+    if birth_date.year() < min_year {
+        return Err(CodeError::BirthDateTooOld {
+            birth_date,
+            min_year,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the name contains at least a letter and only letters and separators, returning `empty_error` if it's empty
+fn validate_name(name: &str, empty_error: CodeError) -> Result<(), CodeError> {
+    if name.trim().is_empty() {
+        return Err(empty_error);
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphabetic() && !utils::is_separator(c))
+    {
+        return Err(CodeError::InvalidCharacter(c));
+    }
+    if !name.chars().any(|c| c.is_ascii_alphabetic()) {
+        return Err(CodeError::NoLetters(name.to_string()));
+    }
+    Ok(())
+}
+
+fn assemble_code(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    location_code: &str,
+    birth_date: NaiveDate,
+    min_birth_year: i32,
+    normalization: NameNormalization,
+) -> Result<String, CodeError> {
+    let code = assemble_code_parts(
+        name,
+        surname,
+        sex,
+        location_code,
+        birth_date,
+        min_birth_year,
+        normalization,
+    )
+    .map(|code| code.to_string());
+    match &code {
+        Ok(code) => log!(
+            debug,
+            "Generated code {code} with location code {location_code}"
+        ),
+        Err(e) => log!(
+            debug,
+            "Can't generate the code of {name:?} {surname:?}: {e}"
+        ),
+    }
+    code
+}
+
+fn assemble_code_parts(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    location_code: &str,
+    birth_date: NaiveDate,
+    min_birth_year: i32,
+    normalization: NameNormalization,
+) -> Result<GeneratedCode, CodeError> {
+    let name = &normalization.normalize(name);
+    let surname = &normalization.normalize(surname);
+    validate_name(name, CodeError::EmptyName)?;
+    validate_name(surname, CodeError::EmptySurname)?;
+    validate_birth_date(birth_date, min_birth_year)?;
+    let location_code = location_code.to_ascii_uppercase();
+    if !algorithm::is_belfiore_code(&location_code) {
+        return Err(CodeError::InvalidLocationCode(location_code));
+    }
+    let name_code = String::from_iter(algorithm::name_letters(name));
+    let surname_code = String::from_iter(algorithm::surname_letters(surname));
+    let name_padded = algorithm::is_padded(name);
+    let surname_padded = algorithm::is_padded(surname);
+    let year_code = String::from_iter(algorithm::year_digits(birth_date.year()));
+    let born_month = Month::try_from(birth_date.month() as u8).unwrap();
+    let month_code = get_month_letter(&born_month);
+    let day_code = get_day(birth_date.day(), sex)?;
+    let date_code = format!("{year_code}{month_code}{day_code:0>2}");
+    let preliminary_code = format!("{surname_code}{name_code}{date_code}{location_code}");
+    let control_character = get_control_character(&preliminary_code);
+    Ok(GeneratedCode {
+        surname_code,
+        name_code,
+        date_code,
+        location_code,
+        control_character,
+        surname_padded,
+        name_padded,
+    })
+}
+
+/// Get the day field of the code, checking the day is between 1 and 31 even if it doesn't come from a [NaiveDate]
+fn get_day(day: u32, sex: Sex) -> Result<u32, CodeError> {
+    algorithm::day_number(day, sex == Sex::F).ok_or(CodeError::InvalidDay(day))
+}
+
+/// Generate the code with the given data and the Belfiore code of the birth place, like [generate_code_from_codes], keeping its parts
+pub fn generate_code_parts(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    location_code: &str,
+    birth_date: NaiveDate,
+) -> Result<GeneratedCode, CodeError> {
+    assemble_code_parts(
+        name,
+        surname,
+        sex,
+        location_code,
+        birth_date,
+        DEFAULT_MIN_BIRTH_YEAR,
+        NameNormalization::default(),
+    )
+}
+
+/// Compute the first 15 characters of the code, before the control character is added, with the same data of [generate_code_from_codes]
+pub fn preliminary_code(
+    name: &str,
+    surname: &str,
+    sex: Sex,
+    location_code: &str,
+    birth_date: NaiveDate,
+) -> Result<String, CodeError> {
+    Ok(generate_code_parts(name, surname, sex, location_code, birth_date)?.preliminary_code())
+}
+
+/// Compute the three letters representing the surname in the code, after removing its diacritics as in [NameNormalization::StripDiacritics].\
+/// The surname is validated as during the code generation, so it can't be empty and can contain only letters and separators.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::{surname_code, CodeError};
+///
+/// assert_eq!(surname_code("Rossi").unwrap(), "RSS");
+/// assert_eq!(surname_code("Re").unwrap(), "REX");
+/// assert_eq!(surname_code("Niccolò").unwrap(), "NCC");
+/// assert!(matches!(surname_code(" "), Err(CodeError::EmptySurname)));
+/// assert!(matches!(surname_code("R0ssi"), Err(CodeError::InvalidCharacter('0'))));
+/// ```
+pub fn surname_code(surname: &str) -> Result<String, CodeError> {
+    let surname = &NameNormalization::default().normalize(surname);
+    validate_name(surname, CodeError::EmptySurname)?;
+    Ok(String::from_iter(algorithm::surname_letters(surname)))
+}
+
+/// Compute the three letters representing the name in the code, validated and without diacritics as in [surname_code]
+///
+/// # Examples
+/// ```
+/// use codicefiscale::{name_code, CodeError};
+///
+/// assert_eq!(name_code("Mario").unwrap(), "MRA");
+/// assert_eq!(name_code("Giovanni").unwrap(), "GNN");
+/// assert_eq!(name_code("Zoë").unwrap(), "ZOE");
+/// assert!(matches!(name_code(""), Err(CodeError::EmptyName)));
+/// ```
+pub fn name_code(name: &str) -> Result<String, CodeError> {
+    let name = &NameNormalization::default().normalize(name);
+    validate_name(name, CodeError::EmptyName)?;
+    Ok(String::from_iter(algorithm::name_letters(name)))
+}
+
+/// Represents a generated code divided in its parts, which are joined by its [Display] implementation
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::{generate_code_parts, Sex};
+///
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// let code = generate_code_parts("Mario", "Rossi", Sex::M, "H501", birth_date).unwrap();
+/// assert_eq!(code.date_code(), "80A01");
+/// assert_eq!(code.to_string(), "RSSMRA80A01H501U");
+/// assert!(!code.is_surname_padded());
+///
+/// let code = generate_code_parts("Al", "Re", Sex::M, "H501", birth_date).unwrap();
+/// assert_eq!(code.surname_code(), "REX");
+/// assert!(code.is_surname_padded() && code.is_name_padded());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct GeneratedCode {
+    surname_code: String,
+    name_code: String,
+    date_code: String,
+    location_code: String,
+    control_character: char,
+    surname_padded: bool,
+    name_padded: bool,
+}
+
+impl GeneratedCode {
+    /// The three letters representing the surname
+    pub fn surname_code(&self) -> &str {
+        &self.surname_code
+    }
+
+    /// The three letters representing the name
+    pub fn name_code(&self) -> &str {
+        &self.name_code
+    }
+
+    /// The birth year, month and day, which is increased by 40 for women
+    pub fn date_code(&self) -> &str {
+        &self.date_code
+    }
+
+    /// The Belfiore code of the birth place
+    pub fn location_code(&self) -> &str {
+        &self.location_code
+    }
+
+    /// The control character of the code
+    pub fn control_character(&self) -> char {
+        self.control_character
+    }
+
+    /// Whether the surname has less than three letters and its letters have been padded with `X`, which may mean it was truncated
+    pub fn is_surname_padded(&self) -> bool {
+        self.surname_padded
+    }
+
+    /// Whether the name has less than three letters and its letters have been padded with `X`, which may mean it was truncated
+    pub fn is_name_padded(&self) -> bool {
+        self.name_padded
+    }
+
+    /// The code without the control character
+    pub fn preliminary_code(&self) -> String {
+        format!(
+            "{}{}{}{}",
+            self.surname_code, self.name_code, self.date_code, self.location_code
+        )
+    }
+}
+
+impl Display for GeneratedCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.preliminary_code(), self.control_character)
+    }
+}
+
+/// Represents an error occurred during the code generation
+#[derive(Error, Debug)]
+pub enum CodeError {
+    #[error("Missing required fields: {}", .0.join(", "))]
+    MissingFields(Vec<&'static str>),
+    #[error("Nation {0} not found")]
+    NationNotFound(String),
+    #[error("City {0} not found")]
+    CityNotFound(String),
+    #[error("More cities are named {0}, give the province to choose one")]
+    AmbiguousCity(String),
+    #[error("The birth nation is Italy, the birth city is needed")]
+    BornInItaly,
+    #[error("The birth date {0} is in the future")]
+    BirthDateInFuture(NaiveDate),
+    #[error("The birth date {birth_date} is before {min_year}")]
+    BirthDateTooOld {
+        birth_date: NaiveDate,
+        min_year: i32,
+    },
+    #[error("Invalid preliminary code {0}, it must be made of 15 uppercase letters and digits")]
+    InvalidPreliminaryCode(String),
+    #[error("Invalid code length {0}, it must be 16 characters long")]
+    InvalidCodeLength(usize),
+    #[error("Invalid location code {0}, it must be a letter followed by three digits")]
+    InvalidLocationCode(String),
+    #[error("The name is empty")]
+    EmptyName,
+    #[error("The surname is empty")]
+    EmptySurname,
+    #[error("{0} doesn't contain any letter")]
+    NoLetters(String),
+    #[error(
+        "Invalid character '{0}', names can contain only letters, spaces, apostrophes and hyphens"
+    )]
+    InvalidCharacter(char),
+    #[error("Invalid day {0}, it must be between 1 and 31")]
+    InvalidDay(u32),
+    #[error("Invalid date {year}-{month}-{day}")]
+    InvalidDate { year: i32, month: u32, day: u32 },
+    #[error("Invalid substitution depth {depth}, the code has only {max_depth} digits to replace")]
+    HomocodicDepthTooLarge { depth: u32, max_depth: u32 },
+    #[error("Invalid character '{character}' at position {position} of the code")]
+    InvalidCodeCharacter { character: char, position: usize },
+}
+
+impl CodeError {
+    /// Get the message of the error in the given language, the same of [Display] for [Language::English]
+    pub fn localized_message(&self, language: Language) -> String {
+        if language == Language::English {
+            return self.to_string();
+        }
+        match self {
+            CodeError::MissingFields(fields) => {
+                format!("Campi obbligatori mancanti: {}", fields.join(", "))
+            }
+            CodeError::NationNotFound(nation) => format!("Nazione {nation} non trovata"),
+            CodeError::CityNotFound(city) => format!("Comune {city} non trovato"),
+            CodeError::AmbiguousCity(city) => {
+                format!("Più comuni si chiamano {city}, indica la provincia per sceglierne uno")
+            }
+            CodeError::BornInItaly => {
+                "La nazione di nascita è l'Italia, serve il comune di nascita".to_string()
+            }
+            CodeError::BirthDateInFuture(birth_date) => {
+                format!("La data di nascita {birth_date} è nel futuro")
+            }
+            CodeError::BirthDateTooOld {
+                birth_date,
+                min_year,
+            } => format!("La data di nascita {birth_date} è precedente al {min_year}"),
+            CodeError::InvalidPreliminaryCode(code) => format!(
+                "Codice preliminare {code} non valido, deve essere composto da 15 lettere maiuscole e cifre"
+            ),
+            CodeError::InvalidCodeLength(length) => format!(
+                "Lunghezza del codice {length} non valida, deve essere lungo 16 caratteri"
+            ),
+            CodeError::InvalidLocationCode(code) => format!(
+                "Codice catastale {code} non valido, deve essere una lettera seguita da tre cifre"
+            ),
+            CodeError::EmptyName => "Il nome è vuoto".to_string(),
+            CodeError::EmptySurname => "Il cognome è vuoto".to_string(),
+            CodeError::NoLetters(name) => format!("{name} non contiene alcuna lettera"),
+            CodeError::InvalidCharacter(c) => format!(
+                "Carattere '{c}' non valido, i nomi possono contenere solo lettere, spazi, apostrofi e trattini"
+            ),
+            CodeError::InvalidDay(day) => {
+                format!("Giorno {day} non valido, deve essere compreso tra 1 e 31")
+            }
+            CodeError::InvalidDate { year, month, day } => {
+                format!("Data {year}-{month}-{day} non valida")
+            }
+            CodeError::HomocodicDepthTooLarge { depth, max_depth } => format!(
+                "Profondità di sostituzione {depth} non valida, il codice ha solo {max_depth} cifre da sostituire"
+            ),
+            CodeError::InvalidCodeCharacter {
+                character,
+                position,
+            } => format!("Carattere '{character}' non valido in posizione {position} del codice"),
+        }
+    }
+}
+
+/// Any error of the library, to propagate with `?` the errors of functions using both the database and the code generation or parsing
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::parser::parse_code;
+/// use codicefiscale::{generate_code_from_codes, Error, Sex};
+///
+/// fn birth_date_of(name: &str, surname: &str, birth_date: NaiveDate) -> Result<NaiveDate, Error> {
+///     let code = generate_code_from_codes(name, surname, Sex::M, "H501", birth_date)?;
+///     Ok(parse_code(&code)?.birth_date)
+/// }
+///
+/// let birth_date = NaiveDate::from_ymd_opt(1980, 1, 1).unwrap();
+/// assert_eq!(birth_date_of("Mario", "Rossi", birth_date).unwrap(), birth_date);
+/// assert!(matches!(birth_date_of("", "Rossi", birth_date), Err(Error::Code(_))));
+/// ```
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Code(#[from] CodeError),
+    #[error(transparent)]
+    Parse(#[from] parser::ParseError),
+    #[cfg(feature = "db")]
+    #[error(transparent)]
+    Db(#[from] db_utils::DbError),
+}
+
+impl Error {
+    /// Get the message of the error in the given language, the same of [Display] for [Language::English]
+    pub fn localized_message(&self, language: Language) -> String {
+        match self {
+            Error::Code(e) => e.localized_message(language),
+            Error::Parse(e) => e.localized_message(language),
+            #[cfg(feature = "db")]
+            Error::Db(e) => e.localized_message(language),
+        }
+    }
+}
+
+/// The language of the messages returned by the `localized_message` method of the errors
+///
+/// # Examples
+/// ```
+/// use codicefiscale::{CodeError, Language};
+///
+/// assert_eq!(CodeError::EmptyName.localized_message(Language::English), "The name is empty");
+/// assert_eq!(CodeError::EmptyName.localized_message(Language::Italian), "Il nome è vuoto");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Language {
+    #[default]
+    English,
+    Italian,
+}
+
+/// Build a code field by field, as an alternative to [generate_code].
+///
+/// The birth place can be given either as a Belfiore code with [CodeBuilder::location_code]
+/// or as a [Nation] and a [City], following the same rules of [generate_code].
+#[derive(Default)]
+pub struct CodeBuilder {
+    name: Option<String>,
+    surname: Option<String>,
+    sex: Option<Sex>,
+    birth_date: Option<NaiveDate>,
+    location_code: Option<String>,
+    nation: Option<Nation>,
+    city: Option<City>,
+    min_birth_year: Option<i32>,
+    normalization: NameNormalization,
+}
+
+impl CodeBuilder {
+    /// Create an empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the surname
+    pub fn surname(mut self, surname: impl Into<String>) -> Self {
+        self.surname = Some(surname.into());
+        self
+    }
+
+    /// Set the sex
+    pub fn sex(mut self, sex: Sex) -> Self {
+        self.sex = Some(sex);
+        self
+    }
+
+    /// Set the birth date
+    pub fn birth_date(mut self, birth_date: NaiveDate) -> Self {
+        self.birth_date = Some(birth_date);
+        self
+    }
+
+    /// Set the Belfiore code of the birth place, it takes precedence over the nation and the city
+    pub fn location_code(mut self, location_code: impl Into<String>) -> Self {
+        self.location_code = Some(location_code.into());
+        self
+    }
+
+    /// Set the birth nation
+    pub fn nation(mut self, nation: Nation) -> Self {
+        self.nation = Some(nation);
+        self
+    }
+
+    /// Set the birth city, needed only if the nation is Italy or is not set
+    pub fn city(mut self, city: City) -> Self {
+        self.city = Some(city);
+        self
+    }
+
+    /// Set the earliest accepted birth year, [DEFAULT_MIN_BIRTH_YEAR] if not set
+    pub fn min_birth_year(mut self, min_birth_year: i32) -> Self {
+        self.min_birth_year = Some(min_birth_year);
+        self
+    }
+
+    /// Set how the letters with diacritics of the name and the surname are replaced, [NameNormalization::StripDiacritics] if not set
+    pub fn name_normalization(mut self, normalization: NameNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Generate the code, failing if any required field is missing or the birth date isn't valid
+    pub fn build(self) -> Result<String, CodeError> {
+        let location_code = match (self.location_code, self.nation, self.city) {
+            (Some(location_code), _, _) => Some(location_code),
+            (None, Some(nation), _) if nation.nation_code != "0000" => Some(nation.nation_code),
+            (None, _, Some(city)) => Some(city.city_code),
+            _ => None,
+        };
+        match (
+            self.name,
+            self.surname,
+            self.sex,
+            self.birth_date,
+            location_code,
+        ) {
+            (Some(name), Some(surname), Some(sex), Some(birth_date), Some(location_code)) => {
+                assemble_code(
+                    &name,
+                    &surname,
+                    sex,
+                    &location_code,
+                    birth_date,
+                    self.min_birth_year.unwrap_or(DEFAULT_MIN_BIRTH_YEAR),
+                    self.normalization,
+                )
+            }
+            (name, surname, sex, birth_date, location_code) => {
+                let missing = [
+                    ("name", name.is_none()),
+                    ("surname", surname.is_none()),
+                    ("sex", sex.is_none()),
+                    ("birth_date", birth_date.is_none()),
+                    ("location", location_code.is_none()),
+                ]
+                .into_iter()
+                .filter(|f| f.1)
+                .map(|f| f.0)
+                .collect();
+                Err(CodeError::MissingFields(missing))
+            }
+        }
+    }
 }
 
-/// Generate the homocodic version of the code, in case of homonymy
-pub fn generate_homocodic_from_code(code: &str, substitution_depth: u32) -> String {
-    let preliminary_code: String = code
-        .char_indices()
-        .filter(|c| c.0 != 15)
-        .map(|c| c.1)
-        .collect();
+/// Generate the homocodic version of the code, in case of homonymy.\
+/// The code must be 16 characters long, made of uppercase letters in the surname, the name, the month and the first character of the location code, and of digits or homocodic letters elsewhere.\
+/// Its control character is replaced with the recomputed one.\
+/// The substitution depth can't exceed the number of digits left in the code, which are 7 in a code without substitutions.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::{generate_homocodic_from_code, CodeError};
+///
+/// assert_eq!(generate_homocodic_from_code("RSSMRA80A01H501U", 1).unwrap(), "RSSMRA80A01H50MM");
+/// assert_eq!(generate_homocodic_from_code("RSSMRA80A01H501U", 7).unwrap(), "RSSMRAULALMHRLMD");
+/// assert!(matches!(
+///     generate_homocodic_from_code("RSSMRA80A01H501U", 8),
+///     Err(CodeError::HomocodicDepthTooLarge { depth: 8, max_depth: 7 })
+/// ));
+/// assert!(generate_homocodic_from_code("RSSMRA80A01H501", 1).is_err());
+/// assert!(matches!(
+///     generate_homocodic_from_code("RSSMRA80A01H50-U", 1),
+///     Err(CodeError::InvalidCodeCharacter { character: '-', position: 15 })
+/// ));
+/// assert!(matches!(
+///     generate_homocodic_from_code("RSSMRA8AA01H501U", 1),
+///     Err(CodeError::InvalidCodeCharacter { character: 'A', position: 8 })
+/// ));
+/// assert!(generate_homocodic_from_code("rssmra80a01h501u", 1).is_err());
+/// ```
+pub fn generate_homocodic_from_code(
+    code: &str,
+    substitution_depth: u32,
+) -> Result<String, CodeError> {
+    let length = code.chars().count();
+    if length != 16 {
+        return Err(CodeError::InvalidCodeLength(length));
+    }
+    check_code_characters(code)?;
+    let preliminary_code = &code[..code.len() - 1];
+    let max_depth = homocodic_max_depth(preliminary_code);
+    if substitution_depth > max_depth {
+        return Err(CodeError::HomocodicDepthTooLarge {
+            depth: substitution_depth,
+            max_depth,
+        });
+    }
     let preliminary_code =
-        generate_homocodic_preliminary_code(&preliminary_code, substitution_depth);
+        generate_homocodic_preliminary_code(preliminary_code, substitution_depth);
     let check_code = get_control_character(&preliminary_code);
-    format!("{preliminary_code}{check_code}")
+    Ok(format!("{preliminary_code}{check_code}"))
 }
 
-fn generate_homocodic_preliminary_code(preliminary_code: &str, substitution_depth: u32) -> String {
-    if substitution_depth == 0 {
-        return preliminary_code.to_string();
-    }
-    let mut substitution_depth = substitution_depth;
-    let mut new_preliminary_code: Vec<char> = vec![];
-    for c in preliminary_code.chars().rev() {
-        let new_c = if c.is_ascii_digit() && substitution_depth != 0 {
-            substitution_depth -= 1;
-            let c = c.to_digit(10).unwrap();
-            *HOMOCODIC_LOOKUP_TABLE.get(&c).unwrap()
-        } else {
-            c
+/// Generate the code and all its homocodic versions up to `max_depth` substitutions, the code itself first.\
+/// Depths beyond the number of digits in the code are ignored, since they wouldn't add new versions.
+pub fn homocodic_variants(code: &str, max_depth: u32) -> Result<Vec<String>, CodeError> {
+    let max_depth = match code.get(..15) {
+        Some(preliminary_code) => max_depth.min(homocodic_max_depth(preliminary_code)),
+        None => max_depth,
+    };
+    (0..=max_depth)
+        .map(|substitution_depth| generate_homocodic_from_code(code, substitution_depth))
+        .collect()
+}
+
+/// Find the first homocodic version of the code, from the depth 1 up to `max_depth`, for which `is_taken` returns `false`.\
+/// Returns `None` if all of them are taken or if the code is invalid.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::next_free_homocodic;
+///
+/// let registry = ["RSSMRA80A01H501U", "RSSMRA80A01H50MM"];
+/// let is_taken = |code: &str| registry.contains(&code);
+/// assert_eq!(next_free_homocodic("RSSMRA80A01H501U", is_taken, 7).unwrap(), "RSSMRA80A01H5LMX");
+/// assert!(next_free_homocodic("RSSMRA80A01H501U", is_taken, 1).is_none());
+/// ```
+pub fn next_free_homocodic(
+    code: &str,
+    is_taken: impl Fn(&str) -> bool,
+    max_depth: u32,
+) -> Option<String> {
+    homocodic_variants(code, max_depth)
+        .ok()?
+        .into_iter()
+        .skip(1)
+        .find(|variant| !is_taken(variant))
+}
+
+/// Checks that every character of the 16 characters long code is allowed in its position, so the control character can be computed
+fn check_code_characters(code: &str) -> Result<(), CodeError> {
+    for (position, character) in code.chars().enumerate() {
+        let is_valid = match position {
+            0..=5 | 8 | 11 => character.is_ascii_uppercase(),
+            15 => character.is_ascii_uppercase() || character.is_ascii_digit(),
+            _ => character.is_ascii_digit() || algorithm::homocodic_digit(character).is_some(),
         };
-        new_preliminary_code.push(new_c);
+        if !is_valid {
+            return Err(CodeError::InvalidCodeCharacter {
+                character,
+                position: position + 1,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Number of digits of the preliminary code which can still be replaced by a homocodic substitution
+fn homocodic_max_depth(preliminary_code: &str) -> u32 {
+    preliminary_code
+        .chars()
+        .filter(char::is_ascii_digit)
+        .count() as u32
+}
+
+/// Checks if `code` is the `expected` one or one of its homocodic versions, ignoring the case.\
+/// Returns `false` if `expected` isn't a valid code.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::code_matches;
+///
+/// assert!(code_matches("RSSMRA80A01H501U", "rssmra80a01h501u"));
+/// assert!(code_matches("RSSMRA80A01H501U", "RSSMRA80A01H50MM"));
+/// assert!(!code_matches("RSSMRA80A01H501U", "RSSMRA80A01H502V"));
+/// ```
+pub fn code_matches(expected: &str, code: &str) -> bool {
+    let code = code.to_ascii_uppercase();
+    homocodic_variants(&expected.to_ascii_uppercase(), u32::MAX)
+        .is_ok_and(|variants| variants.contains(&code))
+}
+
+fn generate_homocodic_preliminary_code(preliminary_code: &str, substitution_depth: u32) -> String {
+    let mut chars = ['0'; 15];
+    for (c, preliminary_c) in chars.iter_mut().zip(preliminary_code.chars()) {
+        *c = preliminary_c;
+    }
+    String::from_iter(algorithm::homocodic_preliminary_code(
+        chars,
+        substitution_depth,
+    ))
+}
+
+/// How the letters with diacritics of a name are replaced before computing the code
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum NameNormalization {
+    /// Remove the diacritics, like `ü` to `u`
+    #[default]
+    StripDiacritics,
+    /// Expand the German and Scandinavian letters, like `ü` to `ue` and `å` to `aa`, and remove the other diacritics
+    Expanded,
+}
+
+impl NameNormalization {
+    /// Replace the letters with diacritics of the name with ASCII letters.\
+    /// Letters like `ß` and `æ` are always expanded, to `ss` and `ae`.
+    ///
+    /// # Examples
+    /// ```
+    /// use codicefiscale::NameNormalization;
+    ///
+    /// assert_eq!(NameNormalization::StripDiacritics.normalize("Müller"), "Muller");
+    /// assert_eq!(NameNormalization::Expanded.normalize("Müller"), "Mueller");
+    /// assert_eq!(NameNormalization::Expanded.normalize("Weiß"), "Weiss");
+    /// ```
+    pub fn normalize(self, name: &str) -> String {
+        let mut normalized = String::with_capacity(name.len());
+        for c in name.chars() {
+            let replacement = match self {
+                NameNormalization::StripDiacritics => utils::strip_diacritic(c),
+                NameNormalization::Expanded => {
+                    utils::expand_letter(c).or_else(|| utils::strip_diacritic(c))
+                }
+            };
+            match replacement {
+                Some(replacement) if c.is_uppercase() => {
+                    let mut replacement = replacement.chars();
+                    normalized.extend(replacement.next().map(|c| c.to_ascii_uppercase()));
+                    normalized.extend(replacement);
+                }
+                Some(replacement) => normalized.push_str(replacement),
+                None => normalized.push(c),
+            }
+        }
+        normalized
     }
-    new_preliminary_code.into_iter().rev().collect()
 }
 
-/// Represent a person sex
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+/// Represent a person sex.\
+/// The codes are generated only for one of these two, and [parser::sex_from_code] returns an error when a code gives neither.
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, ValueEnum, Serialize, Deserialize,
+)]
 pub enum Sex {
     /// Male
     #[value(alias("M"))]
@@ -150,92 +1255,91 @@ pub enum Sex {
     F,
 }
 
-fn get_month_letter(month: &Month) -> char {
-    const MONTH_LETTERS: [char; 12] = ['A', 'B', 'C', 'D', 'E', 'H', 'L', 'M', 'P', 'R', 'S', 'T'];
-    let month_num = month.number_from_month() as usize;
-    MONTH_LETTERS[month_num - 1]
+/// Get the letter representing the given month in the code
+pub fn get_month_letter(month: &Month) -> char {
+    algorithm::month_letter(month.number_from_month()).unwrap()
 }
 
-fn get_year(year: &str) -> String {
-    let year_char: Vec<_> = year.chars().collect();
-    assert_eq!(year_char.len(), 4);
-    year_char[2..4].iter().collect()
+/// Get the month represented by the given letter in the code, ignoring the case.\
+/// Returns `None` if the letter doesn't represent any month.
+pub fn month_from_letter(c: char) -> Option<Month> {
+    let month = algorithm::month_from_letter(c)?;
+    Month::try_from(month as u8).ok()
 }
 
-fn get_day(day: u32, sex: Sex) -> u32 {
-    match sex {
-        Sex::M => day,
-        Sex::F => day + 40,
-    }
+/// Compute the control character of the given preliminary code, made of the first 15 uppercase alphanumeric characters of a code.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::control_character;
+///
+/// assert_eq!(control_character("RSSMRA80A01H501").unwrap(), 'U');
+/// assert!(control_character("rssmra80a01h501").is_err());
+/// ```
+pub fn control_character(preliminary_code: &str) -> Result<char, CodeError> {
+    check_preliminary_code(preliminary_code)?;
+    Ok(get_control_character(preliminary_code))
 }
 
-fn extract_surname_letters(name: &str) -> String {
-    let mut name_consonants: Vec<char> = name.chars().filter(utils::is_consonant).collect();
-    let mut name_vowels: Vec<char> = name.chars().filter(utils::is_vowel).collect();
-    let mut name_code = vec![];
-    name_code.append(&mut name_consonants);
-    name_code.append(&mut name_vowels);
-    let name_code: Vec<_> = name_code.chunks(3).collect();
-    let name_code = name_code.first().unwrap();
-    let name_code: String = name_code.to_vec().iter().collect();
-    let name_code = format!("{:X<3}", name_code);
-    name_code.to_ascii_uppercase()
+/// The intermediate values computed to get the control character of a preliminary code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlBreakdown {
+    /// The sum of the values of the characters in the odd positions, counting from 1
+    pub odd_sum: u32,
+    /// The sum of the values of the characters in the even positions, counting from 1
+    pub even_sum: u32,
+    /// The remainder of the division of the total sum by 26
+    pub remainder: u32,
+    /// The letter in the alphabet at the position of the remainder, counting from 0
+    pub control_character: char,
 }
 
-fn extract_name_letters(name: &str) -> String {
-    let mut name_consonants: Vec<char> = name.chars().filter(utils::is_consonant).collect();
-    let mut name_vowels: Vec<char> = name.chars().filter(utils::is_vowel).collect();
-    if name_consonants.len() <= 3 {
-        let mut name_code = vec![];
-        name_code.append(&mut name_consonants);
-        name_code.append(&mut name_vowels);
-        let name_code: Vec<_> = name_code.chunks(3).collect();
-        let name_code = name_code.first().unwrap();
-        let name_code: String = name_code.to_vec().iter().collect();
-        let name_code = format!("{:X<3}", name_code);
-        name_code.to_ascii_uppercase()
-    } else {
-        let name_code = [name_consonants[0], name_consonants[2], name_consonants[3]];
-        let name_code: String = name_code.iter().collect();
-        name_code.to_ascii_uppercase()
+/// Compute the sums leading to the control character of the given preliminary code, as in [control_character]
+///
+/// # Examples
+/// ```
+/// use codicefiscale::control_breakdown;
+///
+/// let breakdown = control_breakdown("RSSMRA80A01H501").unwrap();
+/// assert_eq!((breakdown.odd_sum, breakdown.even_sum), (61, 37));
+/// assert_eq!(breakdown.remainder, 20);
+/// assert_eq!(breakdown.control_character, 'U');
+/// assert!(control_breakdown("RSSMRA80A01H50").is_err());
+/// ```
+pub fn control_breakdown(preliminary_code: &str) -> Result<ControlBreakdown, CodeError> {
+    check_preliminary_code(preliminary_code)?;
+    let (mut odd_sum, mut even_sum) = (0, 0);
+    for (i, c) in preliminary_code.chars().enumerate() {
+        if i % 2 == 0 {
+            odd_sum += algorithm::odd_value(c).expect("The preliminary code is alphanumeric");
+        } else {
+            even_sum += algorithm::even_value(c).expect("The preliminary code is alphanumeric");
+        }
     }
+    let remainder = (odd_sum + even_sum) % 26;
+    Ok(ControlBreakdown {
+        odd_sum,
+        even_sum,
+        remainder,
+        control_character: (b'A' + remainder as u8) as char,
+    })
 }
 
-fn get_control_character(preliminary_code: &str) -> char {
-    let even_characters: Vec<char> = preliminary_code
-        .char_indices()
-        .filter(|c| c.0 % 2 == 1)
-        .map(|c| c.1)
-        .collect();
-    let even_sum: u32 = even_characters_lookup(even_characters).iter().sum();
-    let odd_characters: Vec<char> = preliminary_code
-        .char_indices()
-        .filter(|c| c.0 % 2 == 0)
-        .map(|c| c.1)
-        .collect();
-    let odd_sum: u32 = odd_characters_lookup(odd_characters).iter().sum();
-    let code_sum: u32 = (even_sum + odd_sum) % 26;
-    ALPHABET_VEC[code_sum as usize]
-}
-
-fn even_characters_lookup(chars: Vec<char>) -> Vec<u32> {
-    chars
-        .into_iter()
-        .map(|c| c.to_ascii_uppercase())
-        .map(|c| {
-            if c.is_ascii_digit() {
-                c.to_digit(10).unwrap()
-            } else {
-                (ALPHABET_VEC.binary_search(&c).unwrap()) as u32
-            }
-        })
-        .collect()
+/// Checks that the preliminary code is made of 15 uppercase alphanumeric characters
+fn check_preliminary_code(preliminary_code: &str) -> Result<(), CodeError> {
+    let is_valid = preliminary_code.len() == 15
+        && preliminary_code
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    if !is_valid {
+        return Err(CodeError::InvalidPreliminaryCode(
+            preliminary_code.to_string(),
+        ));
+    }
+    Ok(())
 }
 
-fn odd_characters_lookup(chars: Vec<char>) -> Vec<u32> {
-    chars
-        .into_iter()
-        .map(|c| c.to_ascii_uppercase())
-        .map(|c| *ODD_LOOKUP_TABLE.get(&c).unwrap())
-        .collect()
+fn get_control_character(preliminary_code: &str) -> char {
+    algorithm::control_character(preliminary_code)
+        .expect("The preliminary code must be made of letters and digits")
 }
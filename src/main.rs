@@ -8,60 +8,675 @@
 use std::{
     env,
     fs::{create_dir, File},
-    io::Write,
-    path::Path,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
 };
 
 use chrono::NaiveDate;
 use clap::{CommandFactory, Parser, ValueEnum};
 use clap_complete::Shell;
+#[cfg(feature = "fuzzy")]
+use codicefiscale::db_utils::search_city_fuzzy;
 use codicefiscale::{
-    db_utils::{check_db_not_empty, establish_connection, populate_db, search_city, search_nation},
-    generate_code, generate_homocodic_from_code,
-    models::{City, Nation},
+    batch::{generate_csv_with, BatchMode, BatchOptions, Collision},
+    db_utils::{
+        check_db_not_empty_or_explain, database_url_from_env, db_info, establish_connection,
+        find_city_by_code, find_nation_by_code, iter_cities, iter_nations,
+        populate_db_with_connection, search_city_exact, search_nation, DataFormat, DbConnection,
+        DbError, DuplicateStrategy, InvalidCodeStrategy, PopulateMode, DEFAULT_SEARCH_LIMIT,
+    },
+    generate_code_from_codes, generate_homocodic_from_code, homocodic_variants,
+    parser::{decode_homocodic, location_code, parse_code, parse_code_with_pivot, validate_code},
+    validate_birth_date, CodeBuilder, PersonRecord, Sex, DEFAULT_MIN_BIRTH_YEAR,
 };
+use serde::Serialize;
 
 mod cli;
 
+/// The database and the data files chosen with the global options
+struct Settings {
+    /// The url given with `--database-url`, or the one taken from the environment
+    database_url: Option<String>,
+    nations_file: PathBuf,
+    cities_file: PathBuf,
+}
+
+impl Settings {
+    /// Checks that the database is populated, explaining how to create it otherwise
+    fn check_database(&self) -> Result<(), DbError> {
+        check_db_not_empty_or_explain(self.database_url.as_deref())
+    }
+
+    /// Connect to the database, exiting if it isn't populated
+    fn open_database(&self) -> DbConnection {
+        if let Err(e) = self.check_database() {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        self.connect()
+    }
+
+    /// Connect to the database without checking its content
+    fn connect(&self) -> DbConnection {
+        let Some(database_url) = &self.database_url else {
+            eprintln!("The database url must be given with --database-url or the DATABASE_URL environment variable");
+            process::exit(1);
+        };
+        establish_connection(database_url)
+    }
+}
+
 fn main() {
-    let args: String = env::args().collect();
-    assert!(args.is_ascii(), "Data must be in ASCII format!");
     let cli = cli::Cli::parse();
+    let settings = Settings {
+        database_url: cli.database_url.or_else(database_url_from_env),
+        nations_file: cli.nations_file,
+        cities_file: cli.cities_file,
+    };
     match cli.command {
-        cli::Commands::Generate(args) => generate(args),
-        cli::Commands::BuildDatabase => populate_db(),
+        cli::Commands::Generate(args) => generate(&settings, args),
+        cli::Commands::Validate(args) => validate(&settings, args),
+        cli::Commands::Parse(args) => parse(&settings, args),
+        cli::Commands::Homocodic(args) => homocodic(args),
+        cli::Commands::Batch(args) => batch(&settings, args),
+        cli::Commands::Lookup(args) => lookup(&settings, args),
+        cli::Commands::Export(args) => export(&settings, args),
+        cli::Commands::BuildDatabase(args) => build_database(&settings, args),
+        cli::Commands::DbInfo => print_db_info(&settings),
         cli::Commands::BuildComplete => build_complete_file(),
     }
 }
 
-fn generate(args: cli::GenerateArgs) {
-    if let Err(e) = check_db_not_empty() {
+fn generate(settings: &Settings, args: cli::GenerateArgs) {
+    let location_code = find_location_code(settings, &args).unwrap_or_else(|e| {
         eprintln!("{e}");
-        return;
-    }
-    let mut conn = establish_connection();
-    let city: Vec<City> = search_city(&mut conn, &args.city);
-    let nation: Vec<Nation> = search_nation(&mut conn, &args.nation);
-    let city = city.into_iter().next().expect("City not found");
-    let nation = nation.into_iter().next().expect("Nation not found");
-    let code = generate_code(
-        args.name,
-        args.surname,
+        process::exit(1);
+    });
+    let code = generate_code_from_codes(
+        &args.name,
+        &args.surname,
         args.sex,
-        nation,
-        city,
+        &location_code,
         args.birth_date,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let homocodic_code = args
+        .substitution_depth
+        .map(|substitution_depth| generate_homocodic_from_code(&code, substitution_depth))
+        .transpose()
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            process::exit(1);
+        });
+    let code = format_code(&code, args.case, args.group);
+    let homocodic_code = homocodic_code.map(|c| format_code(&c, args.case, args.group));
+    match args.format {
+        cli::OutputFormat::Text if args.quiet => {
+            println!("{code}");
+            if let Some(homocodic_code) = homocodic_code {
+                println!("{homocodic_code}");
+            }
+        }
+        cli::OutputFormat::Text => {
+            println!("Code: {code}");
+            if let Some(homocodic_code) = homocodic_code {
+                println!("Homocodic code: {homocodic_code}");
+            }
+        }
+        cli::OutputFormat::Json => {
+            let output = serde_json::json!({ "code": code, "homocodic": homocodic_code });
+            println!("{output}");
+        }
+    }
+}
+
+/// Format the code for printing in the given case, with its parts separated by spaces if `group` is set
+fn format_code(code: &str, case: cli::LetterCase, group: bool) -> String {
+    let code = match case {
+        cli::LetterCase::Upper => code.to_ascii_uppercase(),
+        cli::LetterCase::Lower => code.to_ascii_lowercase(),
+    };
+    if !group || code.len() != 16 {
+        return code;
+    }
+    format!(
+        "{} {} {} {} {}",
+        &code[0..3],
+        &code[3..6],
+        &code[6..11],
+        &code[11..15],
+        &code[15..]
+    )
+}
+
+/// Get the Belfiore code of the birth place, searching the database only for the nation and the city without a code
+fn find_location_code(settings: &Settings, args: &cli::GenerateArgs) -> Result<String, String> {
+    let mut conn = None;
+    let nation_code = match (&args.nation_code, &args.nation) {
+        (Some(nation_code), _) => Some(nation_code.to_ascii_uppercase()),
+        (None, Some(nation)) => {
+            let conn = conn.get_or_insert_with(|| settings.open_database());
+            let candidates = search_nation(conn, nation, Some(DEFAULT_SEARCH_LIMIT));
+            let found = choose_candidate(
+                candidates,
+                |n| format!("{} {}", n.nation_name, n.nation_code),
+                args.interactive,
+                &format!("nations named {nation}"),
+                "--nation-code",
+            )?;
+            match found {
+                Some(found) => Some(found.nation_code),
+                None => return Err(nation_not_found(conn, nation)),
+            }
+        }
+        // Only the city code was given, the person was born in Italy
+        (None, None) => None,
+    };
+    match nation_code {
+        Some(nation_code) if nation_code != "0000" => {
+            if args.city.is_some() && !args.quiet {
+                eprintln!("Note: the birth city is ignored for people born outside Italy");
+            }
+            Ok(nation_code)
+        }
+        _ => match (&args.city_code, &args.city) {
+            (Some(city_code), _) => Ok(city_code.to_ascii_uppercase()),
+            (None, Some(city)) => {
+                let conn = conn.get_or_insert_with(|| settings.open_database());
+                let candidates = search_city_exact(conn, city, args.province.as_deref())
+                    .map_err(|e| e.to_string())?;
+                let city = choose_candidate(
+                    candidates,
+                    |c| format!("{} ({}) {}", c.city_name, c.province, c.city_code),
+                    args.interactive,
+                    &format!("cities named {city}"),
+                    "--province, --city-code",
+                )?
+                .ok_or_else(|| city_not_found(conn, city))?;
+                if let Some(valid_until) = city.valid_until {
+                    eprintln!(
+                        "Warning: {} was abolished on {valid_until}, check that its code was still in use on the birth date",
+                        city.city_name
+                    );
+                }
+                Ok(city.city_code)
+            }
+            (None, None) => Err("The birth city is needed for people born in Italy".to_string()),
+        },
+    }
+}
+
+/// Explain why the nation wasn't found, checking if it's the name of a city given in the place of the nation
+fn nation_not_found(conn: &mut DbConnection, nation: &str) -> String {
+    let is_city = search_city_exact(conn, nation, None).is_ok_and(|c| !c.is_empty());
+    if is_city {
+        format!("Nation {nation} not found, but there is an italian city with this name.\nThe birth nation comes before the birth city, like in `Italia {nation}`")
+    } else {
+        format!("Nation {nation} not found, check its spelling or rebuild the database with the build-database command")
+    }
+}
+
+/// Explain why the city wasn't found, suggesting the cities with the most similar names
+fn city_not_found(conn: &mut DbConnection, city: &str) -> String {
+    #[cfg(feature = "fuzzy")]
+    {
+        const MIN_SUGGESTION_SCORE: f64 = 0.8;
+        let suggestions: Vec<String> = search_city_fuzzy(conn, city, 3)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, score)| *score >= MIN_SUGGESTION_SCORE)
+            .map(|(c, _)| format!("{} ({})", c.city_name, c.province))
+            .collect();
+        if !suggestions.is_empty() {
+            return format!(
+                "No city matching '{city}' found, did you mean {}?",
+                suggestions.join(", ")
+            );
+        }
+    }
+    #[cfg(not(feature = "fuzzy"))]
+    let _ = conn;
+    format!("City {city} not found among the italian cities, check its spelling or rebuild the database with the build-database command")
+}
+
+/// Choose one of the results of a search, returning `None` if there aren't any.\
+/// If there are many, the user is asked to pick one when `interactive` is set, otherwise the error lists them along with the `hint` flags.
+fn choose_candidate<T>(
+    mut candidates: Vec<T>,
+    describe: impl Fn(&T) -> String,
+    interactive: bool,
+    description: &str,
+    hint: &str,
+) -> Result<Option<T>, String> {
+    if candidates.len() <= 1 {
+        return Ok(candidates.pop());
+    }
+    let list: Vec<String> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("  {}) {}", i + 1, describe(c)))
+        .collect();
+    if !interactive {
+        return Err(format!(
+            "There are many {description}, choose one with {hint} or --interactive:\n{}",
+            list.join("\n")
+        ));
+    }
+    eprintln!("There are many {description}:\n{}", list.join("\n"));
+    loop {
+        eprint!("Choose one from 1 to {}: ", candidates.len());
+        io::stderr().flush().map_err(|e| e.to_string())?;
+        let mut choice = String::new();
+        if io::stdin()
+            .read_line(&mut choice)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Err(format!("No choice made between the {description}"));
+        }
+        match choice.trim().parse::<usize>() {
+            Ok(i) if (1..=candidates.len()).contains(&i) => {
+                return Ok(Some(candidates.swap_remove(i - 1)))
+            }
+            _ => eprintln!("Invalid choice {}", choice.trim()),
+        }
+    }
+}
+
+fn validate(settings: &Settings, args: cli::ValidateArgs) {
+    if let Err(e) = validate_code(&args.code) {
+        eprintln!("Invalid code: {e}");
+        process::exit(1);
+    }
+    println!("Control character: valid");
+    if settings.check_database().is_err() {
+        println!("Location: not checked, database unavailable");
+        return;
+    }
+    let code = location_code(&args.code).unwrap_or_else(|e| {
+        eprintln!("Invalid code: {e}");
+        process::exit(1);
+    });
+    match resolve_location_code(settings, &code) {
+        Some(location) => println!("Location: {location} ({code})"),
+        None => {
+            eprintln!("Invalid code: unknown location code {code}");
+            process::exit(1);
+        }
+    }
+}
+
+fn parse(settings: &Settings, args: cli::ParseArgs) {
+    let parsed = match args.pivot_year {
+        Some(pivot_year) => parse_code_with_pivot(&args.code, pivot_year),
+        None => parse_code(&args.code),
+    };
+    let parsed = parsed.unwrap_or_else(|e| {
+        eprintln!("Invalid code: {e}");
+        process::exit(1);
+    });
+    let sex = match parsed.sex {
+        Sex::M => "male",
+        Sex::F => "female",
+    };
+    println!("Surname: {}", parsed.surname_code);
+    println!("Name: {}", parsed.name_code);
+    println!("Birth date: {}", parsed.birth_date);
+    println!("Sex: {sex}");
+    if settings.check_database().is_err() {
+        println!(
+            "Birth place: {} (not resolved, database unavailable)",
+            parsed.location_code
+        );
+    } else {
+        match resolve_location_code(settings, &parsed.location_code) {
+            Some(location) => println!("Birth place: {location} ({})", parsed.location_code),
+            None => println!("Birth place: {} (unknown code)", parsed.location_code),
+        }
+    }
+    println!(
+        "Homocodic: {}",
+        if parsed.is_homocodic { "yes" } else { "no" }
+    );
+}
+
+/// Print the homocodic versions of the code, counting the depths from its canonical version without substitutions
+fn homocodic(args: cli::HomocodicArgs) {
+    let code = args.code.trim();
+    let canonical_code = parse_code(code)
+        .and_then(|_| decode_homocodic(code))
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid code: {e}");
+            process::exit(1);
+        });
+    let variants = homocodic_variants(&canonical_code, args.max_depth).unwrap_or_else(|e| {
+        eprintln!("Invalid code: {e}");
+        process::exit(1);
+    });
+    for (depth, variant) in variants.iter().enumerate() {
+        println!("{depth}: {variant}");
+    }
+}
+
+fn batch(settings: &Settings, args: cli::BatchArgs) {
+    if let Err(e) = settings.check_database() {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+    let input = File::open(&args.input).unwrap_or_else(|e| {
+        eprintln!("Can't open the input file: {e}");
+        process::exit(1);
+    });
+    let output = File::create(&args.output).unwrap_or_else(|e| {
+        eprintln!("Can't create the output file: {e}");
+        process::exit(1);
+    });
+    let options = BatchOptions {
+        mode: if args.check {
+            BatchMode::Check
+        } else {
+            BatchMode::Generate
+        },
+        check_collisions: args.check_collisions,
+    };
+    let mut conn = settings.connect();
+    let summary = generate_csv_with(input, output, options, |person| {
+        generate_from_record(&mut conn, person)
+    })
+    .unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    if args.check {
+        println!(
+            "Checked {} codes, {} passed, {} failed, {} rows with errors",
+            summary.generated, summary.passed, summary.mismatched, summary.failed
+        );
+    } else {
+        println!(
+            "Generated {} codes, {} rows with errors",
+            summary.generated, summary.failed
+        );
+    }
+    if args.check_collisions {
+        report_collisions(&summary.collisions);
+    }
+}
+
+/// Print the codes generated by more rows, along with their lines in the input file
+fn report_collisions(collisions: &[Collision]) {
+    if collisions.is_empty() {
+        println!("No collisions found");
+        return;
+    }
+    println!("Found {} codes generated by more rows:", collisions.len());
+    for collision in collisions {
+        let lines: Vec<String> = collision.lines.iter().map(u64::to_string).collect();
+        println!("{}: lines {}", collision.code, lines.join(", "));
+    }
+}
+
+/// Generate the code of a row of the batch input file, explaining why its birth place wasn't found
+fn generate_from_record(conn: &mut DbConnection, person: PersonRecord) -> Result<String, String> {
+    let nation = search_nation(conn, &person.birth_nation, Some(DEFAULT_SEARCH_LIMIT))
+        .into_iter()
+        .next()
+        .ok_or_else(|| nation_not_found(conn, &person.birth_nation))?;
+    let mut builder = CodeBuilder::new()
+        .name(person.name)
+        .surname(person.surname)
+        .sex(person.sex)
+        .birth_date(person.birth_date);
+    if nation.nation_code == "0000" {
+        if person.birth_city.trim().is_empty() {
+            return Err("The birth city is needed for people born in Italy".to_string());
+        }
+        let province = person
+            .birth_province
+            .as_deref()
+            .map(str::trim)
+            .filter(|p| !p.is_empty());
+        let mut candidates =
+            search_city_exact(conn, &person.birth_city, province).map_err(|e| e.to_string())?;
+        if candidates.len() > 1 {
+            let list: Vec<String> = candidates
+                .iter()
+                .map(|c| format!("{} ({}) {}", c.city_name, c.province, c.city_code))
+                .collect();
+            return Err(format!(
+                "Ambiguous city {}, choose one of {} with the province column",
+                person.birth_city,
+                list.join(", ")
+            ));
+        }
+        let city = candidates
+            .pop()
+            .ok_or_else(|| city_not_found(conn, &person.birth_city))?;
+        builder = builder.city(city);
+    }
+    builder.nation(nation).build().map_err(|e| e.to_string())
+}
+
+fn lookup(settings: &Settings, args: cli::LookupArgs) {
+    let code = args.code.trim().to_ascii_uppercase();
+    let mut conn = settings.open_database();
+    if let Some(city) = find_city_by_code(&mut conn, &code) {
+        if city.province.is_empty() {
+            println!("City: {}", city.city_name);
+        } else {
+            println!("City: {} ({})", city.city_name, city.province);
+        }
+    } else if let Some(nation) = find_nation_by_code(&mut conn, &code) {
+        println!("Nation: {}", nation.nation_name);
+    } else {
+        eprintln!("Unknown code {code}");
+        process::exit(1);
+    }
+}
+
+fn export(settings: &Settings, args: cli::ExportArgs) {
+    let mut conn = settings.open_database();
+    let file = File::create(&args.output).unwrap_or_else(|e| {
+        eprintln!("Can't create the output file: {e}");
+        process::exit(1);
+    });
+    let exported = match args.table {
+        cli::Table::Cities => iter_cities(&mut conn)
+            .map_err(|e| e.to_string())
+            .and_then(|rows| write_rows(rows, args.format, file)),
+        cli::Table::Nations => iter_nations(&mut conn)
+            .map_err(|e| e.to_string())
+            .and_then(|rows| write_rows(rows, args.format, file)),
+    };
+    match exported {
+        Ok(count) => println!("Exported {count} rows to {}", args.output.display()),
+        Err(e) => {
+            eprintln!("Can't export the table: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Write the rows to the file one at a time, returning how many were written
+fn write_rows<T: Serialize>(
+    rows: impl Iterator<Item = Result<T, DbError>>,
+    format: cli::ExportFormat,
+    file: File,
+) -> Result<usize, String> {
+    let mut count = 0;
+    match format {
+        cli::ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(file);
+            for row in rows {
+                writer
+                    .serialize(row.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+                count += 1;
+            }
+            writer.flush().map_err(|e| e.to_string())?;
+        }
+        cli::ExportFormat::Json => {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(b"[").map_err(|e| e.to_string())?;
+            for row in rows {
+                let separator: &[u8] = if count == 0 { b"\n" } else { b",\n" };
+                writer.write_all(separator).map_err(|e| e.to_string())?;
+                serde_json::to_writer(&mut writer, &row.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+                count += 1;
+            }
+            writer.write_all(b"\n]\n").map_err(|e| e.to_string())?;
+            writer.flush().map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(count)
+}
+
+/// Find the name of the city or the nation with the given Belfiore code
+fn resolve_location_code(settings: &Settings, code: &str) -> Option<String> {
+    let mut conn = settings.connect();
+    if let Some(city) = find_city_by_code(&mut conn, code) {
+        Some(city.city_name)
+    } else {
+        find_nation_by_code(&mut conn, code).map(|n| n.nation_name)
+    }
+}
+
+fn build_database(settings: &Settings, args: cli::BuildDatabaseArgs) {
+    let mode = if args.truncate {
+        PopulateMode::Truncate
+    } else {
+        PopulateMode::Upsert
+    };
+    let duplicates = if args.fail_on_duplicates {
+        DuplicateStrategy::Error
+    } else {
+        DuplicateStrategy::Skip
+    };
+    let invalid_codes = if args.strict {
+        InvalidCodeStrategy::Error
+    } else {
+        InvalidCodeStrategy::Skip
+    };
+    let format = match args.format {
+        cli::DataFormat::GardaInformatica => DataFormat::GardaInformatica,
+        cli::DataFormat::Minimal => DataFormat::Minimal,
+    };
+    let nations_path = (!args.cities_only).then_some(settings.nations_file.as_path());
+    let cities_path = (!args.nations_only).then_some(settings.cities_file.as_path());
+    match populate_db_with_connection(
+        &mut settings.connect(),
+        nations_path,
+        cities_path,
+        format,
+        mode,
+        duplicates,
+        invalid_codes,
+    ) {
+        Ok(stats) => {
+            if stats.skipped_nations > 0 {
+                println!(
+                    "Skipped {} nations with an already used code",
+                    stats.skipped_nations
+                );
+            }
+            if stats.skipped_cities > 0 {
+                println!(
+                    "Skipped {} cities with an already used code",
+                    stats.skipped_cities
+                );
+            }
+            for n in &stats.rejected_nations {
+                println!(
+                    "Skipped the nation {} with the invalid code {}",
+                    n.nation_name, n.nation_code
+                );
+            }
+            for c in &stats.rejected_cities {
+                println!(
+                    "Skipped the city {} with the invalid code {}",
+                    c.city_name, c.city_code
+                );
+            }
+            println!(
+                "Database successfully populated with {} nations and {} cities!",
+                stats.nations, stats.cities
+            )
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the content of the database one `key: value` per line, exiting with an error if it isn't usable
+fn print_db_info(settings: &Settings) {
+    let status = match settings.check_database() {
+        Ok(()) => None,
+        Err(
+            e @ (DbError::CitiesTableEmpty
+            | DbError::NationsTableEmpty
+            | DbError::PendingMigrations),
+        ) => Some(e),
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let mut conn = settings.connect();
+    let info = db_info(&mut conn).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    println!("cities: {}", info.cities);
+    println!("nations: {}", info.nations);
+    // The counts of the population can't be recovered from the rows, so they are missing until the database is populated again
+    let count = |count: Option<i64>| count.map_or("unknown".to_string(), |c| c.to_string());
+    let counts = info.populate_counts;
+    println!(
+        "nations_without_code: {}",
+        count(counts.map(|c| c.nations_without_code))
+    );
+    println!(
+        "skipped_nations: {}",
+        count(counts.map(|c| c.skipped_nations))
+    );
+    println!(
+        "skipped_cities: {}",
+        count(counts.map(|c| c.skipped_cities))
+    );
+    println!("duplicate_codes: {}", info.duplicate_codes.join(","));
+    println!(
+        "migrations: {}",
+        if info.migrations_applied {
+            "current"
+        } else {
+            "pending"
+        }
     );
-    println!("Code: {code}");
-    if let Some(substitution_depth) = args.substitution_depth {
-        let homocodic_code = generate_homocodic_from_code(&code, substitution_depth);
-        println!("Homocodic code: {homocodic_code}");
+    match status {
+        None if info.duplicate_codes.is_empty() => println!("status: ok"),
+        None => {
+            println!("status: duplicate codes");
+            process::exit(1);
+        }
+        Some(e) => {
+            println!("status: {e}");
+            process::exit(1);
+        }
     }
 }
 
 fn parse_birth_date(birth_date: &str) -> Result<NaiveDate, String> {
-    NaiveDate::parse_from_str(birth_date, "%F")
-        .map_err(|_| "Invalid date format, should be YYYY-MM-DD.".to_string())
+    let birth_date = NaiveDate::parse_from_str(birth_date, "%F")
+        .map_err(|_| "Invalid date format, should be YYYY-MM-DD.".to_string())?;
+    validate_birth_date(birth_date, DEFAULT_MIN_BIRTH_YEAR).map_err(|e| e.to_string())?;
+    Ok(birth_date)
 }
 
 fn build_complete_file() {
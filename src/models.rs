@@ -5,52 +5,200 @@
  * Use of this source code is governed by BSD-3-Clause-Clear
  * license that can be found in the LICENSE file
  */
+use chrono::NaiveDate;
+#[cfg(feature = "db")]
 use diesel::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-/// Represents a city in the database
-#[derive(Queryable, Selectable)]
-#[diesel(table_name = crate::schema::cities)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+/// Represents a city in the database.\
+/// Since the coordinates are floating point numbers, it implements [PartialEq] but not [Eq].
+///
+/// # Examples
+/// ```
+/// use codicefiscale::models::City;
+///
+/// let city = || City {
+///     id: 1,
+///     city_name: "Roma".to_string(),
+///     city_code: "H501".to_string(),
+///     province: "RM".to_string(),
+///     is_province: true,
+///     lat: Some(41.89),
+///     lon: Some(12.48),
+///     alternative_name: None,
+///     valid_until: None,
+/// };
+/// assert_eq!(city(), city());
+/// assert_ne!(city(), City { id: 2, ..city() });
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "db", derive(Queryable, Selectable))]
+#[cfg_attr(feature = "db", diesel(table_name = crate::schema::cities))]
+#[cfg_attr(feature = "sqlite", diesel(check_for_backend(diesel::sqlite::Sqlite)))]
+#[cfg_attr(feature = "postgres", diesel(check_for_backend(diesel::pg::Pg)))]
 pub struct City {
     pub id: i32,
     pub city_name: String,
     pub city_code: String,
+    pub province: String,
+    pub is_province: bool,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// The German or French name of the bilingual cities, like Bozen for Bolzano
+    pub alternative_name: Option<String>,
+    /// The last day of the city, if it has been abolished or merged into another one
+    pub valid_until: Option<NaiveDate>,
 }
 
 /// Represents a nation in the database
-#[derive(Queryable, Selectable)]
-#[diesel(table_name = crate::schema::nations)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "db", derive(Queryable, Selectable))]
+#[cfg_attr(feature = "db", diesel(table_name = crate::schema::nations))]
+#[cfg_attr(feature = "sqlite", diesel(check_for_backend(diesel::sqlite::Sqlite)))]
+#[cfg_attr(feature = "postgres", diesel(check_for_backend(diesel::pg::Pg)))]
 pub struct Nation {
     pub id: i32,
     pub nation_name: String,
     pub nation_code: String,
+    /// The initials of the nation, like `DE` for Germania
+    pub iso: String,
 }
 
 /// Represents a city to add to the database
-#[derive(Insertable)]
-#[diesel(table_name = crate::schema::cities)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "db", derive(Insertable))]
+#[cfg_attr(feature = "db", diesel(table_name = crate::schema::cities))]
 pub struct NewCity {
     pub city_name: String,
     pub city_code: String,
+    pub province: String,
+    pub is_province: bool,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    /// The German or French name of the bilingual cities, like Bozen for Bolzano
+    pub alternative_name: Option<String>,
+    /// The last day of the city, if it has been abolished or merged into another one
+    pub valid_until: Option<NaiveDate>,
 }
 
+/// Convert a city of the json file, whose coordinates are parsed with either a dot or a comma as decimal separator.\
+/// Empty, malformed or out of range coordinates become `None`, stored as `NULL` in the database.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::models::{NewCity, NewLoadedCity};
+///
+/// let city = |lat: &str, lon: &str| {
+///     NewCity::from(NewLoadedCity {
+///         province_initials: "RM".to_string(),
+///         istat_code: "058091".to_string(),
+///         mixed_city_name: "Roma".to_string(),
+///         city_name: "Roma".to_string(),
+///         alternative_city_name: String::new(),
+///         is_province: "1".to_string(),
+///         city_code: "H501".to_string(),
+///         lat: lat.to_string(),
+///         lon: lon.to_string(),
+///         surface: "1287,36".to_string(),
+///         overmunicipal_code: "258".to_string(),
+///         abolition_date: None,
+///     })
+/// };
+/// let rome = city("41.8919", " 12,5113 ");
+/// assert_eq!((rome.lat, rome.lon), (Some(41.8919), Some(12.5113)));
+/// assert_eq!((city("", "").lat, city("", "").lon), (None, None));
+/// assert_eq!(city("41°53'", "n/a").lat, None);
+/// assert_eq!(city("91", "12.5").lat, None);
+/// assert_eq!(city("41.9", "-181").lon, None);
+/// ```
+///
+/// The coordinates are read back from the database after loading the file:
+/// ```
+/// use codicefiscale::db_utils::*;
+/// use std::{env, fs};
+///
+/// let dir = env::temp_dir().join(format!("codicefiscale-doctest-coordinates-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let cities_file = dir.join("gi_comuni.json");
+/// let city = |name: &str, code: &str, lat: &str, lon: &str| {
+///     format!(
+///         r#"{{"sigla_provincia": "RM", "codice_istat": "", "denominazione_ita_altra": "{name}", "denominazione_ita": "{name}",
+///         "denominazione_altra": "", "flag_capoluogo": "", "codice_belfiore": "{code}", "lat": "{lat}", "lon": "{lon}",
+///         "superficie_kmq": "", "codice_sovracomunale": ""}}"#
+///     )
+/// };
+/// let rome = city("Roma", "H501", "41.8919", "12.5113");
+/// let fiumicino = city("Fiumicino", "M297", "", "not a number");
+/// fs::write(&cities_file, format!("[{rome}, {fiumicino}]")).unwrap();
+///
+/// let mut conn = establish_connection_in_memory();
+/// populate_db_with_connection(
+///     &mut conn,
+///     None,
+///     Some(&cities_file),
+///     DataFormat::GardaInformatica,
+///     PopulateMode::default(),
+///     DuplicateStrategy::default(),
+///     InvalidCodeStrategy::default(),
+/// )
+/// .unwrap();
+/// fs::remove_dir_all(&dir).unwrap();
+/// let rome = find_city_by_code(&mut conn, "H501").unwrap();
+/// assert!((41.0..42.0).contains(&rome.lat.unwrap()));
+/// assert!((12.0..13.0).contains(&rome.lon.unwrap()));
+/// let fiumicino = find_city_by_code(&mut conn, "M297").unwrap();
+/// assert_eq!((fiumicino.lat, fiumicino.lon), (None, None));
+/// ```
 impl From<NewLoadedCity> for NewCity {
     fn from(value: NewLoadedCity) -> Self {
         NewCity {
             city_name: value.city_name,
             city_code: value.city_code,
+            province: value.province_initials,
+            is_province: parse_flag(&value.is_province),
+            lat: parse_coordinate(&value.lat, 90.0),
+            lon: parse_coordinate(&value.lon, 180.0),
+            alternative_name: Some(value.alternative_city_name.trim().to_string())
+                .filter(|name| !name.is_empty()),
+            valid_until: value.abolition_date.as_deref().and_then(parse_date),
         }
     }
 }
 
+/// Parse a coordinate of the json file, discarding malformed or out of range values
+fn parse_coordinate(coordinate: &str, max: f64) -> Option<f64> {
+    coordinate
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|c| c.abs() <= max)
+}
+
+/// Parse a date of the json file, written as `YYYY-MM-DD` or `DD/MM/YYYY`, discarding empty or malformed values
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    let date = date.trim();
+    NaiveDate::parse_from_str(date, "%F")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%d/%m/%Y"))
+        .ok()
+}
+
+/// Parse a flag of the json file, which can be written as a number or as a word
+fn parse_flag(flag: &str) -> bool {
+    matches!(
+        flag.trim().to_ascii_uppercase().as_str(),
+        "1" | "S" | "SI" | "Y" | "YES" | "TRUE"
+    )
+}
+
 /// Represents a nation to add to the database
-#[derive(Insertable)]
-#[diesel(table_name = crate::schema::nations)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "db", derive(Insertable))]
+#[cfg_attr(feature = "db", diesel(table_name = crate::schema::nations))]
 pub struct NewNation {
     pub nation_name: String,
     pub nation_code: String,
+    pub iso: String,
 }
 
 impl From<NewLoadedNation> for NewNation {
@@ -58,6 +206,7 @@ impl From<NewLoadedNation> for NewNation {
         NewNation {
             nation_name: value.nation_name,
             nation_code: value.nation_code,
+            iso: value.nation_initials.trim().to_ascii_uppercase(),
         }
     }
 }
@@ -85,6 +234,9 @@ pub struct NewLoadedCity {
     pub surface: String,
     #[serde(rename = "codice_sovracomunale")]
     pub overmunicipal_code: String,
+    /// The date the city was abolished, found only in the files listing the abolished cities
+    #[serde(rename = "data_soppressione", default)]
+    pub abolition_date: Option<String>,
 }
 
 /// Represents a nation as in the json file
@@ -99,3 +251,51 @@ pub struct NewLoadedNation {
     #[serde(rename = "denominazione_cittadinanza")]
     pub citizen_name: String,
 }
+
+/// Represents a city in a minimal json file, an array of objects like `{"name": "Roma", "code": "H501"}`
+#[derive(Deserialize)]
+pub struct MinimalLoadedCity {
+    pub name: String,
+    pub code: String,
+    /// The initials of the province, like `RM` for Roma
+    #[serde(default)]
+    pub province: String,
+    /// The last day of the city, if it has been abolished
+    #[serde(default)]
+    pub valid_until: Option<String>,
+}
+
+impl From<MinimalLoadedCity> for NewCity {
+    fn from(value: MinimalLoadedCity) -> Self {
+        NewCity {
+            city_name: value.name,
+            city_code: value.code,
+            province: value.province.trim().to_ascii_uppercase(),
+            is_province: false,
+            lat: None,
+            lon: None,
+            alternative_name: None,
+            valid_until: value.valid_until.as_deref().and_then(parse_date),
+        }
+    }
+}
+
+/// Represents a nation in a minimal json file, an array of objects like `{"name": "GERMANIA", "code": "Z112"}`
+#[derive(Deserialize)]
+pub struct MinimalLoadedNation {
+    pub name: String,
+    pub code: String,
+    /// The initials of the nation, like `DE` for Germania
+    #[serde(default)]
+    pub iso: String,
+}
+
+impl From<MinimalLoadedNation> for NewNation {
+    fn from(value: MinimalLoadedNation) -> Self {
+        NewNation {
+            nation_name: value.name,
+            nation_code: value.code,
+            iso: value.iso.trim().to_ascii_uppercase(),
+        }
+    }
+}
@@ -0,0 +1,510 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use chrono::{Datelike, Local, NaiveDate};
+use std::fmt::{self, Display};
+use thiserror::Error;
+
+use crate::resolver::LocationResolver;
+use crate::{algorithm, get_control_character, month_from_letter, Language, Sex};
+
+/// Positions of the characters that can be replaced by a letter in a homocodic code
+const HOMOCODIC_POSITIONS: [usize; 7] = [6, 7, 9, 10, 12, 13, 14];
+
+/// Represents an error found in an existing code
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("The code must be 16 characters long, found {0}")]
+    InvalidLength(usize),
+    #[error("Invalid character '{0}' in the code")]
+    InvalidCharacter(char),
+    #[error("Invalid control character: expected '{expected}', found '{found}'")]
+    InvalidControlCharacter { expected: char, found: char },
+    #[error("Invalid month letter '{0}'")]
+    InvalidMonth(char),
+    #[error("The code doesn't contain a valid birth date")]
+    InvalidDate,
+    #[error(
+        "Invalid day {0}, it must be between 1 and 31 for men and between 41 and 71 for women"
+    )]
+    InvalidDay(u32),
+    #[error("The date {0} is before the birth date")]
+    DateBeforeBirth(NaiveDate),
+    #[error("Invalid surname letters {0}, they must be three letters")]
+    InvalidSurnameSegment(String),
+    #[error("Invalid name letters {0}, they must be three letters")]
+    InvalidNameSegment(String),
+    #[error("Invalid location code {0}, it must start with a letter")]
+    InvalidLocationSegment(String),
+}
+
+impl ParseError {
+    /// Get the message of the error in the given language, the same of [Display] for [Language::English]
+    pub fn localized_message(&self, language: Language) -> String {
+        if language == Language::English {
+            return self.to_string();
+        }
+        match self {
+            ParseError::InvalidLength(length) => {
+                format!("Il codice deve essere lungo 16 caratteri, trovati {length}")
+            }
+            ParseError::InvalidCharacter(c) => format!("Carattere '{c}' non valido nel codice"),
+            ParseError::InvalidControlCharacter { expected, found } => format!(
+                "Carattere di controllo non valido: atteso '{expected}', trovato '{found}'"
+            ),
+            ParseError::InvalidMonth(c) => format!("Lettera del mese '{c}' non valida"),
+            ParseError::InvalidDate => {
+                "Il codice non contiene una data di nascita valida".to_string()
+            }
+            ParseError::InvalidDay(day) => format!(
+                "Giorno {day} non valido, deve essere compreso tra 1 e 31 per gli uomini e tra 41 e 71 per le donne"
+            ),
+            ParseError::DateBeforeBirth(date) => {
+                format!("La data {date} è precedente alla data di nascita")
+            }
+            ParseError::InvalidSurnameSegment(letters) => format!(
+                "Lettere del cognome {letters} non valide, devono essere tre lettere"
+            ),
+            ParseError::InvalidNameSegment(letters) => {
+                format!("Lettere del nome {letters} non valide, devono essere tre lettere")
+            }
+            ParseError::InvalidLocationSegment(code) => format!(
+                "Codice catastale {code} non valido, deve iniziare con una lettera"
+            ),
+        }
+    }
+}
+
+/// Represents the data contained in a code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParsedCode {
+    pub surname_code: String,
+    pub name_code: String,
+    pub birth_date: NaiveDate,
+    /// The day as written in the code, increased by 40 for women
+    pub raw_day: u32,
+    /// The day of the month of the birth date
+    pub day: u32,
+    pub sex: Sex,
+    pub location_code: String,
+    pub is_homocodic: bool,
+}
+
+/// A code whose characters, control character and birth date have been validated, in uppercase.\
+/// The data contained in the code is parsed once, when the code is validated.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::parser::{parse_code, FiscalCode, ParseError};
+/// use codicefiscale::Sex;
+///
+/// let code = FiscalCode::try_from("rssmra80a01h501u").unwrap();
+/// assert_eq!(code.as_ref(), "RSSMRA80A01H501U");
+/// assert_eq!(code.location_code(), "H501");
+/// assert_eq!(code.sex(), Sex::M);
+/// assert_eq!(code, FiscalCode::try_from("RSSMRA80A01H501U").unwrap());
+/// assert_eq!(code.parsed(), &parse_code("RSSMRA80A01H501U").unwrap());
+/// assert!(format!("{code:?}").contains("RSSMRA80A01H501U"));
+/// assert!(matches!(
+///     FiscalCode::try_from("RSSMRA80A01H501A").unwrap_err(),
+///     ParseError::InvalidControlCharacter { expected: 'U', found: 'A' }
+/// ));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FiscalCode {
+    code: String,
+    parsed: ParsedCode,
+}
+
+impl TryFrom<&str> for FiscalCode {
+    type Error = ParseError;
+
+    fn try_from(code: &str) -> Result<Self, Self::Error> {
+        let parsed = parse_code(code)?;
+        Ok(FiscalCode {
+            code: code.to_ascii_uppercase(),
+            parsed,
+        })
+    }
+}
+
+impl FiscalCode {
+    /// The birth date, assuming it's not after the year the code was validated as in [parse_code]
+    pub fn birth_date(&self) -> NaiveDate {
+        self.parsed.birth_date
+    }
+
+    /// The sex of the person
+    pub fn sex(&self) -> Sex {
+        self.parsed.sex
+    }
+
+    /// The Belfiore code of the birth place, without any homocodic substitution
+    pub fn location_code(&self) -> String {
+        self.parsed.location_code.clone()
+    }
+
+    /// Whether some digits of the code have been replaced by letters, in case of homonymy
+    pub fn is_homocodic(&self) -> bool {
+        self.parsed.is_homocodic
+    }
+
+    /// All the data contained in the code
+    pub fn parsed(&self) -> &ParsedCode {
+        &self.parsed
+    }
+}
+
+impl AsRef<str> for FiscalCode {
+    fn as_ref(&self) -> &str {
+        &self.code
+    }
+}
+
+impl Display for FiscalCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+/// Checks the length, the characters and the control character of the given code
+pub fn validate_code(code: &str) -> Result<(), ParseError> {
+    let length = code.chars().count();
+    if length != 16 {
+        return Err(ParseError::InvalidLength(length));
+    }
+    if let Some(c) = code.chars().find(|c| !c.is_ascii_alphanumeric()) {
+        return Err(ParseError::InvalidCharacter(c));
+    }
+    // The lookup ignores the case, so the code is checked without making an uppercase copy
+    let expected = get_control_character(&code[..15]);
+    let found = code.chars().last().unwrap().to_ascii_uppercase();
+    if expected != found {
+        return Err(ParseError::InvalidControlCharacter { expected, found });
+    }
+    Ok(())
+}
+
+/// Replace the control character of the given code with the one computed from its first 15 characters, in uppercase.\
+/// The first 15 characters must be a plausible code: letters for the surname, the name and the first character of the location code,
+/// digits or homocodic letters elsewhere, a valid month and a valid birth date. Otherwise the error found is returned.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::parser::{repair_control, ParseError};
+///
+/// assert_eq!(repair_control("rssmra80a01h501x").unwrap(), "RSSMRA80A01H501U");
+/// assert_eq!(repair_control("RSSMRA80A01H50MA").unwrap(), "RSSMRA80A01H50MM");
+/// assert!(matches!(repair_control("RSSMRA80Z01H501U"), Err(ParseError::InvalidMonth('Z'))));
+/// assert!(matches!(repair_control("RSS1RA80A01H501U"), Err(ParseError::InvalidNameSegment(_))));
+/// ```
+pub fn repair_control(code: &str) -> Result<String, ParseError> {
+    let length = code.chars().count();
+    if length != 16 {
+        return Err(ParseError::InvalidLength(length));
+    }
+    if let Some(c) = code.chars().find(|c| !c.is_ascii_alphanumeric()) {
+        return Err(ParseError::InvalidCharacter(c));
+    }
+    let code = code.to_ascii_uppercase();
+    let preliminary_code = &code[..15];
+    let repaired_code = format!(
+        "{preliminary_code}{}",
+        get_control_character(preliminary_code)
+    );
+    parse_code(&repaired_code)?;
+    Ok(repaired_code)
+}
+
+/// Decode the given code, assuming the birth date is not after the current year.\
+/// See [parse_code_with_pivot] for how the century of the birth date is chosen.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::control_character;
+/// use codicefiscale::parser::{parse_code, ParseError};
+///
+/// let code = |preliminary_code: &str| {
+///     format!("{preliminary_code}{}", control_character(preliminary_code).unwrap())
+/// };
+/// assert_eq!(parse_code(&code("RSSMRA80A01H501")).unwrap().location_code, "H501");
+/// assert!(matches!(parse_code(&code("R55MRA80A01H501")), Err(ParseError::InvalidSurnameSegment(_))));
+/// assert!(matches!(parse_code(&code("RSSMR480A01H501")), Err(ParseError::InvalidNameSegment(_))));
+/// assert!(matches!(parse_code(&code("RSSMRA80A011501")), Err(ParseError::InvalidLocationSegment(_))));
+/// assert!(matches!(parse_code(&code("RSSMRA80A010000")), Err(ParseError::InvalidLocationSegment(_))));
+/// assert!(matches!(parse_code(&code("RSSMRA8AA01H501")), Err(ParseError::InvalidCharacter('A'))));
+/// assert!(matches!(parse_code(&code("RSSMRA80F01H501")), Err(ParseError::InvalidMonth('F'))));
+/// ```
+///
+/// Decoding a generated code gives back the letters, the sex, the birth date and the location code, with a pivot year from the birth year to 99 years later.
+/// The days of the women, from 41 to 71, and the names padded with `X` are decoded too:
+/// ```
+/// use chrono::NaiveDate;
+/// use codicefiscale::parser::{parse_code, parse_code_with_pivot};
+/// use codicefiscale::{generate_code_from_codes, Sex};
+///
+/// let people = [
+///     ("Mario", "Rossi", "RSSMRA"),
+///     ("Al", "Re", "REXLAX"),
+///     ("O", "Ai", "AIXOXX"),
+///     ("Io", "Bo", "BOXIOX"),
+///     ("Maria Grazia", "D'Angelo", "DNGMGR"),
+///     ("Kyu", "Yamamoto", "YMMKYU"),
+/// ];
+/// let dates = [(1930, 1, 1), (1968, 2, 29), (1999, 12, 31), (2004, 7, 15)];
+/// for (name, surname, letters) in people {
+///     for (year, month, day) in dates {
+///         for sex in [Sex::M, Sex::F] {
+///             for location_code in ["H501", "Z112", "A952"] {
+///                 let birth_date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+///                 let code = generate_code_from_codes(name, surname, sex, location_code, birth_date).unwrap();
+///                 let raw_day = if sex == Sex::F { day + 40 } else { day };
+///                 for pivot_year in [year, year + 1, year + 99] {
+///                     let parsed = parse_code_with_pivot(&code, pivot_year).unwrap();
+///                     assert_eq!(format!("{}{}", parsed.surname_code, parsed.name_code), letters);
+///                     assert_eq!(parsed.sex, sex);
+///                     assert_eq!(parsed.birth_date, birth_date);
+///                     assert_eq!((parsed.raw_day, parsed.day), (raw_day, day));
+///                     assert_eq!(parsed.location_code, location_code);
+///                 }
+///                 assert_eq!(parse_code(&code).unwrap().birth_date, birth_date);
+///                 // Before the birth year, or a century after it, the birth date moves to the other century
+///                 let birth_date_with_pivot = |pivot_year| parse_code_with_pivot(&code, pivot_year).unwrap().birth_date;
+///                 assert_eq!(birth_date_with_pivot(year - 1), NaiveDate::from_ymd_opt(year - 100, month, day).unwrap());
+///                 assert_eq!(birth_date_with_pivot(year + 100), NaiveDate::from_ymd_opt(year + 100, month, day).unwrap());
+///             }
+///         }
+///     }
+/// }
+/// ```
+pub fn parse_code(code: &str) -> Result<ParsedCode, ParseError> {
+    parse_code_with_pivot(code, Local::now().year())
+}
+
+/// Decode the given code using `pivot_year` as the latest possible birth year.\
+/// Since the code stores only the last two digits of the birth year, the birth year is the latest one not after `pivot_year` ending with those digits.
+/// With a pivot year of 2024, `24` is decoded as 2024 while `25` is decoded as 1925.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::control_character;
+/// use codicefiscale::parser::{parse_code_with_pivot, ParseError};
+///
+/// let code = |day: &str| {
+///     let preliminary_code = format!("RSSMRA80A{day}H501");
+///     format!("{preliminary_code}{}", control_character(&preliminary_code).unwrap())
+/// };
+/// assert_eq!(parse_code_with_pivot(&code("31"), 2024).unwrap().day, 31);
+/// assert!(matches!(parse_code_with_pivot(&code("32"), 2024), Err(ParseError::InvalidDay(32))));
+/// let parsed = parse_code_with_pivot(&code("71"), 2024).unwrap();
+/// assert_eq!((parsed.raw_day, parsed.day), (71, 31));
+/// assert!(matches!(parse_code_with_pivot(&code("72"), 2024), Err(ParseError::InvalidDay(72))));
+/// ```
+pub fn parse_code_with_pivot(code: &str, pivot_year: i32) -> Result<ParsedCode, ParseError> {
+    let decoded_code = decode_homocodic(code)?;
+    let code = code.to_ascii_uppercase();
+    let year: i32 = decoded_code[6..8].parse().unwrap();
+    let mut year = pivot_year - pivot_year.rem_euclid(100) + year;
+    if year > pivot_year {
+        year -= 100;
+    }
+    let month_letter = decoded_code.chars().nth(8).unwrap();
+    let month = month_from_letter(month_letter).ok_or(ParseError::InvalidMonth(month_letter))?;
+    let raw_day: u32 = decoded_code[9..11].parse().unwrap();
+    let (sex, day) = match raw_day {
+        1..=31 => (Sex::M, raw_day),
+        41..=71 => (Sex::F, raw_day - 40),
+        _ => return Err(ParseError::InvalidDay(raw_day)),
+    };
+    let birth_date = NaiveDate::from_ymd_opt(year, month.number_from_month(), day)
+        .ok_or(ParseError::InvalidDate)?;
+    Ok(ParsedCode {
+        surname_code: decoded_code[0..3].to_string(),
+        name_code: decoded_code[3..6].to_string(),
+        birth_date,
+        raw_day,
+        day,
+        sex,
+        location_code: decoded_code[11..15].to_string(),
+        is_homocodic: decoded_code != code,
+    })
+}
+
+/// Get the sex of the person with the given code, which is female if the day field exceeds 40.\
+/// A day field outside the ranges of men, from 1 to 31, and women, from 41 to 71, gives [ParseError::InvalidDay] instead of a guess.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::parser::{sex_from_code, ParseError};
+/// use codicefiscale::Sex;
+///
+/// assert!(sex_from_code("RSSMRA80A41H501Y").unwrap() == Sex::F);
+/// assert!(matches!(sex_from_code("RSSMRA80A35H501K"), Err(ParseError::InvalidDay(35))));
+/// ```
+pub fn sex_from_code(code: &str) -> Result<Sex, ParseError> {
+    Ok(parse_code(code)?.sex)
+}
+
+/// Get the birth date of the person with the given code, using `pivot_year` as in [parse_code_with_pivot]
+pub fn birth_date_from_code(code: &str, pivot_year: i32) -> Result<NaiveDate, ParseError> {
+    Ok(parse_code_with_pivot(code, pivot_year)?.birth_date)
+}
+
+/// Compute the completed years on `on_date` of the person with the given code, assuming they weren't born after `on_date`.\
+/// People born on the 29th of February complete their years on the 1st of March in non leap years.
+pub fn age_from_code(code: &str, on_date: NaiveDate) -> Result<u32, ParseError> {
+    let birth_date = parse_code_with_pivot(code, on_date.year())?.birth_date;
+    if birth_date > on_date {
+        return Err(ParseError::DateBeforeBirth(on_date));
+    }
+    let mut age = on_date.year() - birth_date.year();
+    if (on_date.month(), on_date.day()) < (birth_date.month(), birth_date.day()) {
+        age -= 1;
+    }
+    Ok(age as u32)
+}
+
+/// Extract the Belfiore code of the birth place from the given code, reverting any homocodic substitution
+pub fn location_code(code: &str) -> Result<String, ParseError> {
+    let decoded_code = decode_homocodic(code)?;
+    Ok(decoded_code[11..15].to_string())
+}
+
+/// A problem found in a code by [diagnose], either one that makes it invalid or one found in a valid code
+#[derive(Error, Debug)]
+pub enum Diagnostic {
+    /// The length, the characters, the control character or the data of the code are invalid
+    #[error(transparent)]
+    Invalid(#[from] ParseError),
+    /// A homocodic letter replaces a digit which is followed by other digits, so the substitutions didn't start from the last one
+    #[error("The homocodic letters don't replace the last digits of the code")]
+    InconsistentHomocodic,
+    /// The location code doesn't belong to any city or nation known by the resolver
+    #[error("No city or nation has the location code {0}")]
+    UnknownLocationCode(String),
+}
+
+impl Diagnostic {
+    /// Get the message of the problem in the given language, the same of [Display] for [Language::English]
+    pub fn localized_message(&self, language: Language) -> String {
+        if language == Language::English {
+            return self.to_string();
+        }
+        match self {
+            Diagnostic::Invalid(e) => e.localized_message(language),
+            Diagnostic::InconsistentHomocodic => {
+                "Le lettere di omocodia non sostituiscono le ultime cifre del codice".to_string()
+            }
+            Diagnostic::UnknownLocationCode(code) => {
+                format!("Nessun comune o nazione ha il codice catastale {code}")
+            }
+        }
+    }
+}
+
+/// Find all the problems of the given code, returning an empty list if it's valid.\
+/// The length and the characters are checked first, then the control character and the data separately, so both can be reported.\
+/// If a resolver is given and the code is otherwise valid, its location code must belong to a known city or nation.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::parser::{diagnose, Diagnostic, ParseError};
+/// use codicefiscale::resolver::MapResolver;
+///
+/// let resolver = MapResolver::new().with_city("Roma", "H501");
+/// assert!(diagnose("RSSMRA80A01H501U", Some(&resolver)).is_empty());
+/// assert!(matches!(diagnose("RSSMRA80A01H501", None)[..], [Diagnostic::Invalid(ParseError::InvalidLength(15))]));
+/// assert!(matches!(
+///     diagnose("RSSMRA80Z01H501A", None)[..],
+///     [Diagnostic::Invalid(ParseError::InvalidControlCharacter { .. }), Diagnostic::Invalid(ParseError::InvalidMonth('Z'))]
+/// ));
+/// assert!(matches!(diagnose("RSSMRA80A01H5M1G", None)[..], [Diagnostic::InconsistentHomocodic]));
+/// assert!(diagnose("RSSMRA80A01F205X", None).is_empty());
+/// assert!(matches!(&diagnose("RSSMRA80A01F205X", Some(&resolver))[..], [Diagnostic::UnknownLocationCode(code)] if code == "F205"));
+/// ```
+pub fn diagnose(code: &str, resolver: Option<&dyn LocationResolver>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    match validate_code(code) {
+        Err(e @ ParseError::InvalidControlCharacter { .. }) => diagnostics.push(e.into()),
+        Err(e) => return vec![e.into()],
+        Ok(()) => {}
+    }
+    // The data is checked with the right control character, so a wrong one doesn't hide other problems
+    let code = match repair_control(code) {
+        Ok(code) => code,
+        Err(e) => {
+            diagnostics.push(e.into());
+            return diagnostics;
+        }
+    };
+    let mut last_digit_found = false;
+    for position in HOMOCODIC_POSITIONS.iter().rev() {
+        let c = code.as_bytes()[*position];
+        if c.is_ascii_digit() {
+            last_digit_found = true;
+        } else if last_digit_found {
+            diagnostics.push(Diagnostic::InconsistentHomocodic);
+            break;
+        }
+    }
+    if let (Some(resolver), Ok(location_code)) = (resolver, location_code(&code)) {
+        if resolver.has_location_code(&location_code) == Some(false) {
+            diagnostics.push(Diagnostic::UnknownLocationCode(location_code));
+        }
+    }
+    diagnostics
+}
+
+/// Validate the given code and revert all its homocodic substitutions, in uppercase.\
+/// The control character is left untouched.
+///
+/// # Examples
+/// ```
+/// use codicefiscale::parser::{decode_homocodic, ParseError};
+///
+/// assert_eq!(decode_homocodic("RSSMRA80A01H50MM").unwrap(), "RSSMRA80A01H501M");
+/// assert_eq!(decode_homocodic("rssmra80a01h501u").unwrap(), "RSSMRA80A01H501U");
+/// assert!(matches!(decode_homocodic("RSSMRA80A01H50-U"), Err(ParseError::InvalidCharacter('-'))));
+/// ```
+pub fn decode_homocodic(code: &str) -> Result<String, ParseError> {
+    validate_code(code)?;
+    check_segments(&code.to_ascii_uppercase())?;
+    code.to_ascii_uppercase()
+        .char_indices()
+        .map(|c| {
+            if HOMOCODIC_POSITIONS.contains(&c.0) {
+                decode_homocodic_character(c.1).ok_or(ParseError::InvalidCharacter(c.1))
+            } else {
+                Ok(c.1)
+            }
+        })
+        .collect()
+}
+
+/// Checks that the surname, the name and the first character of the location code are letters.\
+/// The other positions are checked while decoding the homocodic substitutions and the birth date.
+fn check_segments(code: &str) -> Result<(), ParseError> {
+    let is_alphabetic = |segment: &str| segment.chars().all(|c| c.is_ascii_alphabetic());
+    if !is_alphabetic(&code[0..3]) {
+        return Err(ParseError::InvalidSurnameSegment(code[0..3].to_string()));
+    }
+    if !is_alphabetic(&code[3..6]) {
+        return Err(ParseError::InvalidNameSegment(code[3..6].to_string()));
+    }
+    if !is_alphabetic(&code[11..12]) {
+        return Err(ParseError::InvalidLocationSegment(code[11..15].to_string()));
+    }
+    Ok(())
+}
+
+/// Revert the homocodic substitution of a single character, if any
+fn decode_homocodic_character(c: char) -> Option<char> {
+    if c.is_ascii_digit() {
+        return Some(c);
+    }
+    algorithm::homocodic_digit(c).and_then(|digit| char::from_digit(digit, 10))
+}
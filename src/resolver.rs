@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Finds the Belfiore code of a birth place from its name
+pub trait LocationResolver {
+    /// Find the Belfiore code of the italian city with the given name
+    fn resolve_city(&self, name: &str) -> Option<String>;
+    /// Find the Belfiore code of the nation with the given name, `0000` for Italy
+    fn resolve_nation(&self, name: &str) -> Option<String>;
+    /// Find the Belfiore codes of all the italian cities with the given name, only in the province with the given initials if any, like `CO` for Como.\
+    /// More codes mean the name is ambiguous. The default implementation doesn't know the provinces and returns only the city found by [resolve_city](LocationResolver::resolve_city).
+    fn resolve_cities(&self, name: &str, _province: Option<&str>) -> Vec<String> {
+        self.resolve_city(name).into_iter().collect()
+    }
+    /// Checks if a city or a nation has the given Belfiore code, returning `None` if the resolver can't tell
+    fn has_location_code(&self, _code: &str) -> Option<bool> {
+        None
+    }
+}
+
+/// A [LocationResolver] backed by maps in memory, useful for tests.\
+/// Names are compared ignoring the case.
+#[derive(Default)]
+pub struct MapResolver {
+    cities: HashMap<String, String>,
+    nations: HashMap<String, String>,
+}
+
+impl MapResolver {
+    /// Create a resolver without any city or nation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a city with its Belfiore code
+    pub fn with_city(mut self, name: &str, code: &str) -> Self {
+        self.cities.insert(name.to_uppercase(), code.to_string());
+        self
+    }
+
+    /// Add a nation with its Belfiore code
+    pub fn with_nation(mut self, name: &str, code: &str) -> Self {
+        self.nations.insert(name.to_uppercase(), code.to_string());
+        self
+    }
+}
+
+impl LocationResolver for MapResolver {
+    fn resolve_city(&self, name: &str) -> Option<String> {
+        self.cities.get(&name.to_uppercase()).cloned()
+    }
+
+    fn resolve_nation(&self, name: &str) -> Option<String> {
+        self.nations.get(&name.to_uppercase()).cloned()
+    }
+
+    fn has_location_code(&self, code: &str) -> Option<bool> {
+        Some(
+            self.cities
+                .values()
+                .chain(self.nations.values())
+                .any(|c| c == code),
+        )
+    }
+}
+
+/// A [LocationResolver] remembering the codes found by another one, shareable across threads.\
+/// Only the names found are remembered, and no more than the capacity if given, so the following searches of the other names still use the wrapped resolver.
+pub struct CachedResolver<R> {
+    resolver: R,
+    capacity: Option<usize>,
+    cities: Mutex<HashMap<String, String>>,
+    nations: Mutex<HashMap<String, String>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Number of searches answered by a [CachedResolver] with and without using the wrapped resolver
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<R: LocationResolver> CachedResolver<R> {
+    /// Create a resolver remembering all the codes found by the given one
+    pub fn new(resolver: R) -> Self {
+        CachedResolver {
+            resolver,
+            capacity: None,
+            cities: Mutex::new(HashMap::new()),
+            nations: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a resolver remembering at most `capacity` cities and `capacity` nations found by the given one
+    pub fn with_capacity(resolver: R, capacity: usize) -> Self {
+        CachedResolver {
+            capacity: Some(capacity),
+            ..Self::new(resolver)
+        }
+    }
+
+    /// Get how many searches have been answered with and without the wrapped resolver
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forget all the codes found, for example after the database is updated
+    pub fn clear(&self) {
+        if let Ok(mut cities) = self.cities.lock() {
+            cities.clear();
+        }
+        if let Ok(mut nations) = self.nations.lock() {
+            nations.clear();
+        }
+    }
+
+    fn resolve_cached(
+        &self,
+        cache: &Mutex<HashMap<String, String>>,
+        name: &str,
+        resolve: impl FnOnce(&R) -> Option<String>,
+    ) -> Option<String> {
+        if let Some(code) = cache.lock().ok()?.get(name) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(code.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        // The lock isn't held while searching, so other threads aren't blocked by a slow resolver
+        let code = resolve(&self.resolver)?;
+        let mut cache = cache.lock().ok()?;
+        if self.capacity.is_none_or(|capacity| cache.len() < capacity) {
+            cache.insert(name.to_string(), code.clone());
+        }
+        Some(code)
+    }
+}
+
+impl<R: LocationResolver> LocationResolver for CachedResolver<R> {
+    fn resolve_city(&self, name: &str) -> Option<String> {
+        self.resolve_cached(&self.cities, name, |r| r.resolve_city(name))
+    }
+
+    fn resolve_nation(&self, name: &str) -> Option<String> {
+        self.resolve_cached(&self.nations, name, |r| r.resolve_nation(name))
+    }
+
+    fn resolve_cities(&self, name: &str, province: Option<&str>) -> Vec<String> {
+        self.resolver.resolve_cities(name, province)
+    }
+
+    fn has_location_code(&self, code: &str) -> Option<bool> {
+        self.resolver.has_location_code(code)
+    }
+}
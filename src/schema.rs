@@ -5,6 +5,12 @@ diesel::table! {
         id -> Integer,
         city_name -> Text,
         city_code -> Text,
+        province -> Text,
+        is_province -> Bool,
+        lat -> Nullable<Double>,
+        lon -> Nullable<Double>,
+        alternative_name -> Nullable<Text>,
+        valid_until -> Nullable<Date>,
     }
 }
 
@@ -13,10 +19,21 @@ diesel::table! {
         id -> Integer,
         nation_name -> Text,
         nation_code -> Text,
+        iso -> Text,
+    }
+}
+
+diesel::table! {
+    populate_stats (id) {
+        id -> Integer,
+        nations_without_code -> BigInt,
+        skipped_nations -> BigInt,
+        skipped_cities -> BigInt,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
     cities,
     nations,
+    populate_stats,
 );
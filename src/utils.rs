@@ -12,7 +12,54 @@ pub fn is_vowel(c: &char) -> bool {
     VOWELS.contains(&c)
 }
 
-/// Check is the given char is a consonant or not
+/// Check is the given char is a consonant or not.\
+/// Like `H`, the letters `J`, `K`, `W`, `X` and `Y` are always consonants, even when `Y` sounds like a vowel.
 pub fn is_consonant(c: &char) -> bool {
-    !is_vowel(c)
+    c.is_ascii_alphabetic() && !is_vowel(c)
+}
+
+/// Check is the given char can separate the words of a name, like the space in `De Luca`
+pub fn is_separator(c: &char) -> bool {
+    [' ', '\'', '-'].contains(c)
+}
+
+/// Get the ASCII letters replacing the given letter without its diacritic, in lowercase.\
+/// Returns `None` if the letter has no diacritic.
+pub fn strip_diacritic(c: char) -> Option<&'static str> {
+    let replacement = match c.to_lowercase().next()? {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' | 'đ' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'ğ' => "g",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'ł' | 'ľ' => "l",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'œ' => "oe",
+        'ř' => "r",
+        'ß' => "ss",
+        'ś' | 'š' | 'ş' => "s",
+        'ť' | 'ţ' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+/// Get the ASCII letters replacing the given German or Scandinavian letter, in lowercase, like `ue` for `ü`.\
+/// Returns `None` for the other letters.
+pub fn expand_letter(c: char) -> Option<&'static str> {
+    let replacement = match c.to_lowercase().next()? {
+        'ä' => "ae",
+        'ö' => "oe",
+        'ü' => "ue",
+        'å' => "aa",
+        'ø' => "oe",
+        _ => return None,
+    };
+    Some(replacement)
 }
@@ -0,0 +1,59 @@
+/*
+ * Copyright (c) 2024 Matteo Franceschini
+ * All rights reserved.
+ *
+ * Use of this source code is governed by BSD-3-Clause-Clear
+ * license that can be found in the LICENSE file
+ */
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use wasm_bindgen::prelude::*;
+
+use crate::{parser, Sex};
+
+/// Generate the code with the Belfiore code of the birth place.\
+/// The sex is `M` or `F` and the birth date is in format `YYYY-MM-DD`.
+#[wasm_bindgen(js_name = generateCode)]
+pub fn generate_code(
+    name: &str,
+    surname: &str,
+    sex: &str,
+    location_code: &str,
+    birth_date: &str,
+) -> Result<String, JsError> {
+    let sex = Sex::from_str(sex, true).map_err(|_| JsError::new(&format!("Invalid sex {sex}")))?;
+    let birth_date = NaiveDate::parse_from_str(birth_date, "%F")
+        .map_err(|_| JsError::new("Invalid date format, should be YYYY-MM-DD."))?;
+    Ok(crate::generate_code_from_codes(
+        name,
+        surname,
+        sex,
+        location_code,
+        birth_date,
+    )?)
+}
+
+/// Checks the length, the characters and the control character of the code, throwing an error if it isn't valid
+#[wasm_bindgen(js_name = validateCode)]
+pub fn validate_code(code: &str) -> Result<(), JsError> {
+    Ok(parser::validate_code(code)?)
+}
+
+/// Decode the code into a JSON object with the fields `surname_code`, `name_code`, `birth_date`, `sex`, `location_code` and `is_homocodic`
+#[wasm_bindgen(js_name = parseCode)]
+pub fn parse_code(code: &str) -> Result<String, JsError> {
+    let parsed = parser::parse_code(code)?;
+    let sex = match parsed.sex {
+        Sex::M => "M",
+        Sex::F => "F",
+    };
+    let parsed = serde_json::json!({
+        "surname_code": parsed.surname_code,
+        "name_code": parsed.name_code,
+        "birth_date": parsed.birth_date.format("%F").to_string(),
+        "sex": sex,
+        "location_code": parsed.location_code,
+        "is_homocodic": parsed.is_homocodic,
+    });
+    Ok(parsed.to_string())
+}